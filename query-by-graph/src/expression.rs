@@ -0,0 +1,579 @@
+use serde::{Deserialize, Serialize};
+use spargebra::algebra::{
+    AggregateExpression, AggregateFunction as AlgebraAggregateFunction,
+    Expression as AlgebraExpression,
+};
+use std::collections::HashMap;
+use spargebra::term::Literal;
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// A structured SPARQL expression as it is used in `FILTER` constraints and
+/// computed variables.
+///
+/// ```json
+/// { "type": "comparison", "operator": ">",
+///   "left": { "type": "variable", "name": "?date" },
+///   "right": { "type": "term", "value": "\"1800-01-01\"^^xsd:dateTime" } }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Expression {
+    /// A variable, e.g. `?date`.
+    Variable { name: String },
+    /// An IRI or a literal written as in SPARQL, e.g. `wd:Q5`, `"de"` or `42`.
+    Term { value: String },
+    Comparison {
+        operator: ComparisonOperator,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    Arithmetic {
+        operator: ArithmeticOperator,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    And { operands: Vec<Expression> },
+    Or { operands: Vec<Expression> },
+    Not { operand: Box<Expression> },
+    /// `operand IN (values)` or `operand NOT IN (values)`.
+    In {
+        operand: Box<Expression>,
+        values: Vec<Expression>,
+        #[serde(default)]
+        negated: bool,
+    },
+    /// A built-in function such as `LANG`, `CONTAINS` or `YEAR`, or a custom
+    /// function given by its IRI.
+    Function {
+        name: String,
+        arguments: Vec<Expression>,
+    },
+    /// An aggregate, e.g. in a `HAVING` condition. Without an argument it
+    /// aggregates all solutions, i.e. `COUNT(*)`.
+    Aggregate {
+        function: AggregateFunction,
+        #[serde(default)]
+        distinct: bool,
+        #[serde(default)]
+        argument: Option<Box<Expression>>,
+        /// Separator of `GROUP_CONCAT`.
+        #[serde(default)]
+        separator: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Sample,
+    GroupConcat,
+}
+
+impl AggregateFunction {
+    fn as_str(self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+            AggregateFunction::Sample => "SAMPLE",
+            AggregateFunction::GroupConcat => "GROUP_CONCAT",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    #[serde(rename = "=")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticOperator {
+    #[serde(rename = "+")]
+    Add,
+    #[serde(rename = "-")]
+    Subtract,
+    #[serde(rename = "*")]
+    Multiply,
+    #[serde(rename = "/")]
+    Divide,
+}
+
+impl ArithmeticOperator {
+    fn as_str(self) -> &'static str {
+        match self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Subtract => "-",
+            ArithmeticOperator::Multiply => "*",
+            ArithmeticOperator::Divide => "/",
+        }
+    }
+}
+
+impl ComparisonOperator {
+    fn as_str(self) -> &'static str {
+        match self {
+            ComparisonOperator::Equal => "=",
+            ComparisonOperator::NotEqual => "!=",
+            ComparisonOperator::Less => "<",
+            ComparisonOperator::LessOrEqual => "<=",
+            ComparisonOperator::Greater => ">",
+            ComparisonOperator::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+/// Renders an expression in SPARQL syntax, without the surrounding `FILTER(...)`.
+pub fn render_expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Variable { name } => {
+            if name.starts_with('?') {
+                name.clone()
+            } else {
+                format!("?{}", name)
+            }
+        }
+        Expression::Term { value } => value.clone(),
+        Expression::Comparison {
+            operator,
+            left,
+            right,
+        } => format!(
+            "{} {} {}",
+            render_operand(left),
+            operator.as_str(),
+            render_operand(right)
+        ),
+        Expression::Arithmetic {
+            operator,
+            left,
+            right,
+        } => format!(
+            "{} {} {}",
+            render_operand(left),
+            operator.as_str(),
+            render_operand(right)
+        ),
+        Expression::And { operands } => operands
+            .iter()
+            .map(|operand| match operand {
+                // && binds stronger than ||
+                Expression::Or { .. } => format!("({})", render_expression(operand)),
+                _ => render_expression(operand),
+            })
+            .collect::<Vec<_>>()
+            .join(" && "),
+        Expression::Or { operands } => operands
+            .iter()
+            .map(render_expression)
+            .collect::<Vec<_>>()
+            .join(" || "),
+        Expression::Not { operand } => format!("!{}", render_operand(operand)),
+        Expression::In {
+            operand,
+            values,
+            negated,
+        } => format!(
+            "{} {}IN ({})",
+            render_operand(operand),
+            if *negated { "NOT " } else { "" },
+            values
+                .iter()
+                .map(render_expression)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expression::Function { name, arguments } => format!(
+            "{}({})",
+            name,
+            arguments
+                .iter()
+                .map(render_expression)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Expression::Aggregate {
+            function,
+            distinct,
+            argument,
+            separator,
+        } => format!(
+            "{}({}{}{})",
+            function.as_str(),
+            if *distinct { "DISTINCT " } else { "" },
+            argument
+                .as_ref()
+                .map(|argument| render_expression(argument))
+                .unwrap_or_else(|| "*".to_string()),
+            separator
+                .as_ref()
+                .map(|separator| format!(
+                    "; SEPARATOR={}",
+                    Literal::new_simple_literal(separator.as_str())
+                ))
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// Renders an operand of an operator, wrapping composite expressions in parentheses.
+fn render_operand(expression: &Expression) -> String {
+    match expression {
+        Expression::Comparison { .. }
+        | Expression::Arithmetic { .. }
+        | Expression::And { .. }
+        | Expression::Or { .. }
+        | Expression::In { .. } => format!("({})", render_expression(expression)),
+        _ => render_expression(expression),
+    }
+}
+
+/// Returns all variables an expression refers to, in order of appearance and
+/// without duplicates.
+pub fn expression_variables(expression: &Expression) -> Vec<String> {
+    fn collect(expression: &Expression, variables: &mut Vec<String>) {
+        match expression {
+            Expression::Variable { .. } => {
+                let name = render_expression(expression);
+                if !variables.contains(&name) {
+                    variables.push(name);
+                }
+            }
+            Expression::Term { .. } => {}
+            Expression::Comparison { left, right, .. }
+            | Expression::Arithmetic { left, right, .. } => {
+                collect(left, variables);
+                collect(right, variables);
+            }
+            Expression::And { operands } | Expression::Or { operands } => {
+                for operand in operands {
+                    collect(operand, variables);
+                }
+            }
+            Expression::Not { operand } => collect(operand, variables),
+            Expression::In {
+                operand, values, ..
+            } => {
+                collect(operand, variables);
+                for value in values {
+                    collect(value, variables);
+                }
+            }
+            Expression::Function { arguments, .. } => {
+                for argument in arguments {
+                    collect(argument, variables);
+                }
+            }
+            Expression::Aggregate { argument, .. } => {
+                if let Some(argument) = argument {
+                    collect(argument, variables);
+                }
+            }
+        }
+    }
+
+    let mut variables = Vec::new();
+    collect(expression, &mut variables);
+    variables
+}
+
+/// Whether an expression contains an aggregate, i.e. can only be evaluated
+/// per group.
+pub fn contains_aggregate(expression: &Expression) -> bool {
+    match expression {
+        Expression::Variable { .. } | Expression::Term { .. } => false,
+        Expression::Comparison { left, right, .. } | Expression::Arithmetic { left, right, .. } => {
+            contains_aggregate(left) || contains_aggregate(right)
+        }
+        Expression::And { operands } | Expression::Or { operands } => operands.iter().any(contains_aggregate),
+        Expression::Not { operand } => contains_aggregate(operand),
+        Expression::In {
+            operand, values, ..
+        } => contains_aggregate(operand) || values.iter().any(contains_aggregate),
+        Expression::Function { arguments, .. } => arguments.iter().any(contains_aggregate),
+        Expression::Aggregate { .. } => true,
+    }
+}
+
+/// Splits an expression into the operands of its top level `&&`.
+pub fn split_conjunction(expression: &AlgebraExpression) -> Vec<&AlgebraExpression> {
+    match expression {
+        AlgebraExpression::And(left, right) => {
+            let mut conjuncts = split_conjunction(left);
+            conjuncts.extend(split_conjunction(right));
+            conjuncts
+        }
+        other => vec![other],
+    }
+}
+
+/// Converts a parsed spargebra expression into the VQG expression model.
+///
+/// Constructs without a structured counterpart are kept as a verbatim term, so
+/// that no constraint is lost on import.
+pub fn algebra_to_expression(expression: &AlgebraExpression) -> Expression {
+    fn comparison(
+        operator: ComparisonOperator,
+        left: &AlgebraExpression,
+        right: &AlgebraExpression,
+    ) -> Expression {
+        Expression::Comparison {
+            operator,
+            left: Box::new(algebra_to_expression(left)),
+            right: Box::new(algebra_to_expression(right)),
+        }
+    }
+
+    fn arithmetic(
+        operator: ArithmeticOperator,
+        left: &AlgebraExpression,
+        right: &AlgebraExpression,
+    ) -> Expression {
+        Expression::Arithmetic {
+            operator,
+            left: Box::new(algebra_to_expression(left)),
+            right: Box::new(algebra_to_expression(right)),
+        }
+    }
+
+    fn flatten(expression: &AlgebraExpression, conjunction: bool, operands: &mut Vec<Expression>) {
+        match (expression, conjunction) {
+            (AlgebraExpression::And(left, right), true) | (AlgebraExpression::Or(left, right), false) => {
+                flatten(left, conjunction, operands);
+                flatten(right, conjunction, operands);
+            }
+            (other, _) => operands.push(algebra_to_expression(other)),
+        }
+    }
+
+    fn function(name: &str, arguments: Vec<Expression>) -> Expression {
+        Expression::Function {
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    match expression {
+        AlgebraExpression::NamedNode(n) => Expression::Term {
+            value: n.to_string(),
+        },
+        AlgebraExpression::Literal(l) => Expression::Term {
+            value: literal_to_string(l),
+        },
+        AlgebraExpression::Variable(v) => Expression::Variable {
+            name: format!("?{}", v.as_str()),
+        },
+        AlgebraExpression::And(..) => {
+            let mut operands = Vec::new();
+            flatten(expression, true, &mut operands);
+            Expression::And { operands }
+        }
+        AlgebraExpression::Or(..) => {
+            let mut operands = Vec::new();
+            flatten(expression, false, &mut operands);
+            Expression::Or { operands }
+        }
+        AlgebraExpression::Equal(l, r) => comparison(ComparisonOperator::Equal, l, r),
+        AlgebraExpression::Greater(l, r) => comparison(ComparisonOperator::Greater, l, r),
+        AlgebraExpression::GreaterOrEqual(l, r) => {
+            comparison(ComparisonOperator::GreaterOrEqual, l, r)
+        }
+        AlgebraExpression::Less(l, r) => comparison(ComparisonOperator::Less, l, r),
+        AlgebraExpression::LessOrEqual(l, r) => comparison(ComparisonOperator::LessOrEqual, l, r),
+        AlgebraExpression::Add(l, r) => arithmetic(ArithmeticOperator::Add, l, r),
+        AlgebraExpression::Subtract(l, r) => arithmetic(ArithmeticOperator::Subtract, l, r),
+        AlgebraExpression::Multiply(l, r) => arithmetic(ArithmeticOperator::Multiply, l, r),
+        AlgebraExpression::Divide(l, r) => arithmetic(ArithmeticOperator::Divide, l, r),
+        AlgebraExpression::Not(inner) => match inner.as_ref() {
+            // spargebra parses `a != b` as `!(a = b)`
+            AlgebraExpression::Equal(l, r) => comparison(ComparisonOperator::NotEqual, l, r),
+            // and `a NOT IN (...)` as `!(a IN (...))`
+            AlgebraExpression::In(operand, values) => Expression::In {
+                operand: Box::new(algebra_to_expression(operand)),
+                values: values.iter().map(algebra_to_expression).collect(),
+                negated: true,
+            },
+            other => Expression::Not {
+                operand: Box::new(algebra_to_expression(other)),
+            },
+        },
+        AlgebraExpression::In(operand, values) => Expression::In {
+            operand: Box::new(algebra_to_expression(operand)),
+            values: values.iter().map(algebra_to_expression).collect(),
+            negated: false,
+        },
+        AlgebraExpression::SameTerm(l, r) => function(
+            "sameTerm",
+            vec![algebra_to_expression(l), algebra_to_expression(r)],
+        ),
+        AlgebraExpression::Bound(v) => function(
+            "BOUND",
+            vec![Expression::Variable {
+                name: format!("?{}", v.as_str()),
+            }],
+        ),
+        AlgebraExpression::If(a, b, c) => function(
+            "IF",
+            vec![
+                algebra_to_expression(a),
+                algebra_to_expression(b),
+                algebra_to_expression(c),
+            ],
+        ),
+        AlgebraExpression::Coalesce(arguments) => function(
+            "COALESCE",
+            arguments.iter().map(algebra_to_expression).collect(),
+        ),
+        AlgebraExpression::FunctionCall(f, arguments) => function(
+            &f.to_string(),
+            arguments.iter().map(algebra_to_expression).collect(),
+        ),
+        other => Expression::Term {
+            value: other.to_string(),
+        },
+    }
+}
+
+/// Converts a parsed spargebra aggregate into an `Expression::Aggregate`.
+/// Custom aggregate functions are not supported.
+pub fn algebra_to_aggregate(aggregate: &AggregateExpression) -> Option<Expression> {
+    match aggregate {
+        AggregateExpression::CountSolutions { distinct } => Some(Expression::Aggregate {
+            function: AggregateFunction::Count,
+            distinct: *distinct,
+            argument: None,
+            separator: None,
+        }),
+        AggregateExpression::FunctionCall {
+            name,
+            expr,
+            distinct,
+        } => {
+            let (function, separator) = match name {
+                AlgebraAggregateFunction::Count => (AggregateFunction::Count, None),
+                AlgebraAggregateFunction::Sum => (AggregateFunction::Sum, None),
+                AlgebraAggregateFunction::Avg => (AggregateFunction::Avg, None),
+                AlgebraAggregateFunction::Min => (AggregateFunction::Min, None),
+                AlgebraAggregateFunction::Max => (AggregateFunction::Max, None),
+                AlgebraAggregateFunction::Sample => (AggregateFunction::Sample, None),
+                AlgebraAggregateFunction::GroupConcat { separator } => {
+                    (AggregateFunction::GroupConcat, separator.clone())
+                }
+                AlgebraAggregateFunction::Custom(_) => return None,
+            };
+            Some(Expression::Aggregate {
+                function,
+                distinct: *distinct,
+                argument: Some(Box::new(algebra_to_expression(expr))),
+                separator,
+            })
+        }
+    }
+}
+
+/// Replaces variables by expressions, e.g. the internal variables spargebra
+/// introduces for aggregates by the aggregates themselves.
+pub fn substitute_variables(
+    expression: &Expression,
+    substitutions: &HashMap<String, Expression>,
+) -> Expression {
+    let substitute = |e: &Expression| substitute_variables(e, substitutions);
+    let substitute_box = |e: &Expression| Box::new(substitute_variables(e, substitutions));
+    match expression {
+        Expression::Variable { .. } => substitutions
+            .get(&render_expression(expression))
+            .cloned()
+            .unwrap_or_else(|| expression.clone()),
+        Expression::Term { .. } => expression.clone(),
+        Expression::Comparison {
+            operator,
+            left,
+            right,
+        } => Expression::Comparison {
+            operator: *operator,
+            left: substitute_box(left),
+            right: substitute_box(right),
+        },
+        Expression::Arithmetic {
+            operator,
+            left,
+            right,
+        } => Expression::Arithmetic {
+            operator: *operator,
+            left: substitute_box(left),
+            right: substitute_box(right),
+        },
+        Expression::And { operands } => Expression::And {
+            operands: operands.iter().map(substitute).collect(),
+        },
+        Expression::Or { operands } => Expression::Or {
+            operands: operands.iter().map(substitute).collect(),
+        },
+        Expression::Not { operand } => Expression::Not {
+            operand: substitute_box(operand),
+        },
+        Expression::In {
+            operand,
+            values,
+            negated,
+        } => Expression::In {
+            operand: substitute_box(operand),
+            values: values.iter().map(substitute).collect(),
+            negated: *negated,
+        },
+        Expression::Function { name, arguments } => Expression::Function {
+            name: name.clone(),
+            arguments: arguments.iter().map(substitute).collect(),
+        },
+        Expression::Aggregate {
+            function,
+            distinct,
+            argument,
+            separator,
+        } => Expression::Aggregate {
+            function: *function,
+            distinct: *distinct,
+            argument: argument.as_ref().map(|argument| substitute_box(argument)),
+            separator: separator.clone(),
+        },
+    }
+}
+
+/// Formats a literal the way it would be written by hand: numbers and booleans
+/// without quotes and XSD datatypes with the `xsd:` prefix.
+pub fn literal_to_string(literal: &Literal) -> String {
+    if literal.language().is_some() {
+        return literal.to_string();
+    }
+    match literal.datatype().as_str().strip_prefix(XSD_NAMESPACE) {
+        Some("string") => Literal::new_simple_literal(literal.value()).to_string(),
+        Some("integer") if literal.value().parse::<i64>().is_ok() => literal.value().to_string(),
+        Some("boolean") if matches!(literal.value(), "true" | "false") => {
+            literal.value().to_string()
+        }
+        Some(local) => format!(
+            "{}^^xsd:{}",
+            Literal::new_simple_literal(literal.value()),
+            local
+        ),
+        None => literal.to_string(),
+    }
+}
@@ -1,10 +1,22 @@
+mod expression;
+mod prefixes;
 mod utils;
+mod wikibase;
 
+pub use crate::expression::{AggregateFunction, ArithmeticOperator, ComparisonOperator, Expression};
+pub use crate::wikibase::{NamespacePrefixes, WikibaseFamily};
+use crate::expression::{
+    algebra_to_aggregate, algebra_to_expression, contains_aggregate, expression_variables,
+    literal_to_string, render_expression, split_conjunction, substitute_variables,
+};
 use crate::utils::set_panic_hook;
+use crate::wikibase::WikibaseRegistry;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, to_string};
-use spargebra::algebra::{GraphPattern, PropertyPathExpression};
-use spargebra::term::{TriplePattern, TermPattern, NamedNodePattern};
+use spargebra::algebra::{
+    Expression as AlgebraExpression, Function, GraphPattern, OrderExpression, PropertyPathExpression,
+};
+use spargebra::term::{GroundTerm, TriplePattern, TermPattern, NamedNodePattern, Variable};
 use spargebra::{Query, SparqlSyntaxError};
 use std::collections::{HashMap, HashSet};
 use wasm_bindgen::prelude::*;
@@ -12,6 +24,18 @@ use wasm_bindgen::prelude::*;
 const INDENTATION_COUNT: usize = 4;
 const WIKIBASE_PREFIX: &str = "PREFIX wikibase: <http://wikiba.se/ontology#>";
 const BD_PREFIX: &str = "PREFIX bd: <http://www.bigdata.com/rdf#>";
+const DEFAULT_LANGUAGES: &str = "[AUTO_LANGUAGE],en";
+const AUTO_LANGUAGE: &str = "[AUTO_LANGUAGE]";
+const RDFS_IRI: &str = "http://www.w3.org/2000/01/rdf-schema#";
+/// The companion variable suffixes with the prefix and the local name of the
+/// predicate the `rdfs:label` strategy binds them with.
+const COMPANIONS: [(&str, &str, &str, &str); 3] = [
+    ("Label", "rdfs", RDFS_IRI, "label"),
+    ("Description", "schema", "http://schema.org/", "description"),
+    ("AltLabel", "skos", "http://www.w3.org/2004/02/skos/core#", "altLabel"),
+];
+const LABEL_SERVICE: &str = "http://wikiba.se/ontology#label";
+const LANGUAGE_PARAMETER: &str = "http://wikiba.se/ontology#language";
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -23,6 +47,87 @@ pub struct Entity {
     pub selected_for_projection: bool,
     #[serde(default)]
     pub distinct: bool,
+    /// `FILTER` constraints on this entity.
+    #[serde(default)]
+    pub filters: Vec<Expression>,
+    /// Aggregates over this entity's variable. An aggregated entity is only
+    /// projected through its aggregates.
+    #[serde(default)]
+    pub aggregates: Vec<Aggregate>,
+    /// Candidate values of this entity's variable, rendered as a `VALUES`
+    /// block. Values are prefixed like the id.
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Marks the target of a Wikibase statement as unknown value or no value
+    /// instead of a concrete value.
+    #[serde(default)]
+    pub snak_type: Option<SnakType>,
+    /// The label columns projected next to this entity's variable if labels
+    /// are retrieved.
+    #[serde(default)]
+    pub companions: Companions,
+}
+
+/// Toggles of the label, description and alias columns of a variable, e.g.
+/// `?personLabel`, `?personDescription` and `?personAltLabel`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Companions {
+    #[serde(default = "default_selected_for_projection")]
+    pub label: bool,
+    #[serde(default)]
+    pub description: bool,
+    #[serde(default)]
+    pub alt_label: bool,
+}
+
+impl Default for Companions {
+    fn default() -> Self {
+        Companions {
+            label: true,
+            description: false,
+            alt_label: false,
+        }
+    }
+}
+
+impl Companions {
+    /// The suffixes of the enabled companion variables.
+    fn suffixes(self) -> Vec<&'static str> {
+        let enabled = [self.label, self.description, self.alt_label];
+        COMPANIONS
+            .iter()
+            .zip(enabled)
+            .filter(|(_, enabled)| *enabled)
+            .map(|((suffix, ..), _)| *suffix)
+            .collect()
+    }
+}
+
+/// The special values of a Wikibase statement.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SnakType {
+    /// "unknown value": the value is a blank node, which the entity's
+    /// variable is filtered with `isBlank`.
+    SomeValue,
+    /// "no value": the source is of the `wdno:` class of the property, the
+    /// entity itself is not rendered.
+    NoValue,
+}
+
+/// An aggregate projection such as `(COUNT(DISTINCT ?student) AS ?students)`.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    #[serde(default)]
+    pub distinct: bool,
+    /// The projected variable, e.g. `?students`.
+    pub alias: String,
+    /// Separator of `GROUP_CONCAT`.
+    #[serde(default)]
+    pub separator: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -39,6 +144,115 @@ pub struct Property {
     pub path_type: Option<String>,
     #[serde(default)]
     pub modifier: Option<String>,
+    /// Render this property's triple inside its own `OPTIONAL { ... }` block.
+    #[serde(default)]
+    pub optional: bool,
+    /// Qualifiers of the Wikibase statement, which turn the edge into a
+    /// statement node pattern.
+    #[serde(default)]
+    pub qualifiers: Vec<Snak>,
+    /// The variable of the statement node. Qualified edges without one get an
+    /// implicit `?statement1`, `?statement2`, ...
+    #[serde(default)]
+    pub statement: Option<String>,
+    /// Projects the variable of the statement node, e.g. to retrieve the
+    /// statements themselves.
+    #[serde(default)]
+    pub statement_selected_for_projection: bool,
+    /// References of the Wikibase statement, rendered through
+    /// `prov:wasDerivedFrom` like qualifiers.
+    #[serde(default)]
+    pub references: Vec<Reference>,
+    /// The rank the statement has to have. Without one, statement nodes
+    /// match statements of any rank.
+    #[serde(default)]
+    pub rank: Option<Rank>,
+    /// Routes the statement through its full value node, see `ValueNode`.
+    #[serde(default)]
+    pub value_node: Option<ValueNode>,
+}
+
+/// The full value node of a statement or qualifier (`psv:` or `pqv:`), whose
+/// components are addressable like other entities, e.g. the precision of a
+/// date or the unit of a quantity.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueNode {
+    /// The variable of the value node. Value nodes without one get an
+    /// implicit `?value1`, `?value2`, ...
+    #[serde(default)]
+    pub variable: Option<String>,
+    #[serde(default)]
+    pub components: Vec<ValueComponent>,
+}
+
+/// A component of a value node with the entity it is bound to, e.g.
+/// `wikibase:timePrecision ?precision`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueComponent {
+    pub kind: ValueKind,
+    pub value: Entity,
+}
+
+/// The components of time, quantity and globe coordinate value nodes, named
+/// after their predicates in the `wikibase:` ontology.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueKind {
+    TimeValue,
+    TimePrecision,
+    TimeTimezone,
+    TimeCalendarModel,
+    QuantityAmount,
+    QuantityUnit,
+    QuantityUpperBound,
+    QuantityLowerBound,
+    GeoLatitude,
+    GeoLongitude,
+    GeoPrecision,
+    GeoGlobe,
+}
+
+/// A rank constraint on a Wikibase statement.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Rank {
+    /// `a wikibase:BestRank`, i.e. the statements `wdt:` returns.
+    Best,
+    /// `wikibase:rank wikibase:PreferredRank`
+    Preferred,
+    /// `wikibase:rank wikibase:NormalRank`
+    Normal,
+    /// `wikibase:rank wikibase:DeprecatedRank`
+    Deprecated,
+    /// Any rank but `wikibase:DeprecatedRank`.
+    NonDeprecated,
+}
+
+/// A property and its value hanging off a statement node, e.g. the qualifier
+/// `pq:P580 ?start`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Snak {
+    pub property: Property,
+    pub value: Entity,
+    /// Routes a qualifier through its full value node, see `ValueNode`.
+    #[serde(default)]
+    pub value_node: Option<ValueNode>,
+}
+
+/// A reference node of a statement with the snaks it cites, e.g.
+/// `pr:P248 wd:Q36578` for "stated in".
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    /// The variable of the reference node. References without one get an
+    /// implicit `?reference1`, `?reference2`, ...
+    #[serde(default)]
+    pub variable: Option<String>,
+    #[serde(default)]
+    pub snaks: Vec<Snak>,
 }
 
 fn default_selected_for_projection() -> bool {
@@ -58,10 +272,418 @@ pub struct Connection {
     pub source: Entity,
     pub target: Entity,
     pub properties: Vec<Property>,
+    /// Render all triples of this connection inside an `OPTIONAL { ... }` block.
+    #[serde(default)]
+    pub optional: bool,
+    /// Optional or negated connections sharing the same group are rendered
+    /// into one common block.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Require the triples of this connection to be absent.
+    #[serde(default)]
+    pub negation: Option<Negation>,
+    /// Filters of the block of this connection which are not attached to a
+    /// single entity, e.g. `FILTER(?a != ?b)` inside an `OPTIONAL` block.
+    #[serde(default)]
+    pub filters: Vec<Expression>,
+    /// `BIND`s of the block of this connection, e.g. inside an `OPTIONAL`
+    /// block.
+    #[serde(default)]
+    pub computed: Vec<ComputedVariable>,
+}
+
+/// How a negated connection is rendered.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Negation {
+    /// `FILTER NOT EXISTS { ... }`
+    NotExists,
+    /// `MINUS { ... }`
+    Minus,
 }
 
-// wasm method, to get a string containing a JSON, which converts it to Connection
-// structs and then calls graph_to_query
+impl Connection {
+    /// The source, the target, the qualifier and reference values and the
+    /// value node components of this connection.
+    fn entities(&self) -> Vec<&Entity> {
+        let mut entities = vec![&self.source, &self.target];
+        for property in &self.properties {
+            for qualifier in &property.qualifiers {
+                entities.push(&qualifier.value);
+                entities.extend(qualifier.value_node.iter().flat_map(|node| &node.components).map(|c| &c.value));
+            }
+            entities.extend(property.references.iter().flat_map(|r| &r.snaks).map(|snak| &snak.value));
+            entities.extend(property.value_node.iter().flat_map(|node| &node.components).map(|c| &c.value));
+        }
+        entities
+    }
+
+    fn entities_mut(&mut self) -> Vec<&mut Entity> {
+        let mut entities = vec![&mut self.source, &mut self.target];
+        for property in &mut self.properties {
+            for qualifier in &mut property.qualifiers {
+                entities.push(&mut qualifier.value);
+                entities.extend(
+                    qualifier.value_node.iter_mut().flat_map(|node| &mut node.components).map(|c| &mut c.value),
+                );
+            }
+            entities.extend(property.references.iter_mut().flat_map(|r| &mut r.snaks).map(|snak| &mut snak.value));
+            entities.extend(property.value_node.iter_mut().flat_map(|node| &mut node.components).map(|c| &mut c.value));
+        }
+        entities
+    }
+}
+
+impl Negation {
+    fn keyword(self) -> &'static str {
+        match self {
+            Negation::NotExists => "FILTER NOT EXISTS",
+            Negation::Minus => "MINUS",
+        }
+    }
+}
+
+/// A complete Visual Query Graph: the connections and everything that is not
+/// bound to a single entity.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryGraph {
+    pub connections: Vec<Connection>,
+    /// Filters which are not attached to an entity, e.g. because they relate
+    /// several variables.
+    #[serde(default)]
+    pub filters: Vec<Expression>,
+    /// Groups of alternative subgraphs, each rendered as `{ ... } UNION { ... }`.
+    #[serde(default)]
+    pub unions: Vec<UnionGroup>,
+    /// Variables computed from an expression instead of being matched.
+    #[serde(default)]
+    pub computed: Vec<ComputedVariable>,
+    /// Nested `{ SELECT ... }` queries, joined with the rest of the graph.
+    #[serde(default)]
+    pub subqueries: Vec<SubQuery>,
+    /// The triples produced by a `CONSTRUCT` query. If empty, the matched
+    /// connections themselves are produced.
+    #[serde(default)]
+    pub template: Vec<Connection>,
+    #[serde(default)]
+    pub settings: QuerySettings,
+}
+
+/// Query level settings which are not part of the graph itself.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySettings {
+    #[serde(default)]
+    pub form: QueryForm,
+    /// `ORDER BY` keys, most significant first.
+    #[serde(default)]
+    pub order_by: Vec<OrderKey>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    /// Explicit `GROUP BY` keys. Without them, the projected variables are
+    /// grouped if there are aggregates.
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// `HAVING` conditions on the groups, only used together with aggregates.
+    #[serde(default)]
+    pub having: Vec<Expression>,
+    /// Re-targets the Wikibase properties of the graph to truthy triples or
+    /// statement nodes. Without one, each property is rendered as given.
+    #[serde(default)]
+    pub statement_mode: Option<StatementMode>,
+    /// Adds the label service like the `add_label_service` flag, with these
+    /// settings.
+    #[serde(default)]
+    pub labels: Option<LabelSettings>,
+    /// The name of the Wikibase instance the graph is queried against, e.g.
+    /// `FactGrid`, whose label languages are the default.
+    #[serde(default)]
+    pub data_source: Option<String>,
+    /// Custom Wikibase instances besides Wikidata, FactGrid and MiMoText.
+    #[serde(default)]
+    pub wikibases: Vec<WikibaseFamily>,
+}
+
+/// How the labels of the projected variables are retrieved.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelSettings {
+    /// Label languages, most preferred first. Without any, the label service
+    /// uses `[AUTO_LANGUAGE],en`.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub strategy: LabelStrategy,
+}
+
+/// Where the labels come from.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum LabelStrategy {
+    /// The Wikibase label service, `SERVICE wikibase:label { ... }`.
+    #[default]
+    Service,
+    /// `rdfs:label` triples in one `OPTIONAL` block per language, for
+    /// endpoints without the label service.
+    RdfsLabel,
+}
+
+impl LabelSettings {
+    /// The languages of `rdfs:label` blocks, most preferred first. The label
+    /// service's `[AUTO_LANGUAGE]` has no counterpart and is skipped.
+    fn label_languages(&self) -> Vec<&str> {
+        let languages: Vec<&str> = self
+            .languages
+            .iter()
+            .map(String::as_str)
+            .filter(|language| *language != AUTO_LANGUAGE)
+            .collect();
+        if languages.is_empty() {
+            vec!["en"]
+        } else {
+            languages
+        }
+    }
+
+    /// The language list of the label service, e.g. `de,en`.
+    fn language_list(&self) -> String {
+        if self.languages.is_empty() {
+            String::from(DEFAULT_LANGUAGES)
+        } else {
+            self.languages.join(",")
+        }
+    }
+}
+
+/// How the Wikibase properties of a graph are rendered.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum StatementMode {
+    /// Direct `wdt:` triples, i.e. only the best ranked statements, wherever
+    /// nothing but the value of the statement is used.
+    Truthy,
+    /// `p:`/`ps:` statement nodes for every property, i.e. statements of any
+    /// rank.
+    Statements,
+}
+
+/// The query form, i.e. what the query returns.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryForm {
+    /// `SELECT`: the projected variables of every match.
+    #[default]
+    Select,
+    /// `ASK`: whether the pattern matches at all.
+    Ask,
+    /// `CONSTRUCT`: a new graph from the `template` of every match.
+    Construct,
+    /// `DESCRIBE`: a description of the resources bound to the projected
+    /// variables.
+    Describe,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderKey {
+    pub variable: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// A nested query with its own graph, projection and solution modifiers,
+/// e.g. to find the top N items per group.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SubQuery {
+    /// A name for the collapsed subquery node.
+    #[serde(default)]
+    pub label: String,
+    pub graph: QueryGraph,
+}
+
+/// A variable whose value is computed from an expression, rendered either as
+/// `BIND(YEAR(?birth) AS ?birthYear)` or as `(STR(?item) AS ?id)` in the
+/// projection.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputedVariable {
+    /// The computed variable, e.g. `?birthYear`.
+    pub alias: String,
+    pub expression: Expression,
+    /// Render as `BIND` inside the WHERE clause, so that the variable can be
+    /// used by filters and other expressions. Otherwise the expression is only
+    /// part of the projection. A `BIND` of a constant is rendered at the
+    /// beginning of its group, any other after the triples of its group.
+    #[serde(default)]
+    pub bind: bool,
+    #[serde(default = "default_selected_for_projection")]
+    pub selected_for_projection: bool,
+}
+
+/// Renders `(expression AS ?alias)`.
+fn render_alias(expression: &Expression, alias: &str) -> String {
+    format!(
+        "({} AS {})",
+        render_expression(expression),
+        render_expression(&Expression::Variable {
+            name: alias.to_string()
+        })
+    )
+}
+
+impl QueryGraph {
+    /// All connections of the graph, including those inside union branches.
+    fn all_connections(&self) -> Vec<&Connection> {
+        fn collect<'a>(connections: &'a [Connection], unions: &'a [UnionGroup], all: &mut Vec<&'a Connection>) {
+            all.extend(connections);
+            for branch in unions.iter().flat_map(|u| &u.branches) {
+                collect(&branch.connections, &branch.unions, all);
+            }
+        }
+
+        let mut all = Vec::new();
+        collect(&self.connections, &self.unions, &mut all);
+        all
+    }
+
+    fn all_connections_mut(&mut self) -> Vec<&mut Connection> {
+        fn collect<'a>(
+            connections: &'a mut [Connection],
+            unions: &'a mut [UnionGroup],
+            all: &mut Vec<&'a mut Connection>,
+        ) {
+            all.extend(connections);
+            for branch in unions.iter_mut().flat_map(|u| &mut u.branches) {
+                collect(&mut branch.connections, &mut branch.unions, all);
+            }
+        }
+
+        let mut all = Vec::new();
+        collect(&mut self.connections, &mut self.unions, &mut all);
+        all
+    }
+
+    /// The computed variables of the graph, of its blocks and of its union
+    /// branches.
+    fn all_computed(&self) -> Vec<&ComputedVariable> {
+        fn collect<'a>(connections: &'a [Connection], unions: &'a [UnionGroup], all: &mut Vec<&'a ComputedVariable>) {
+            all.extend(connections.iter().flat_map(|c| &c.computed));
+            for branch in unions.iter().flat_map(|u| &u.branches) {
+                all.extend(&branch.computed);
+                collect(&branch.connections, &branch.unions, all);
+            }
+        }
+
+        let mut all: Vec<&ComputedVariable> = self.computed.iter().collect();
+        collect(&self.connections, &self.unions, &mut all);
+        all
+    }
+
+    fn all_computed_mut(&mut self) -> Vec<&mut ComputedVariable> {
+        fn collect<'a>(
+            connections: &'a mut [Connection],
+            unions: &'a mut [UnionGroup],
+            all: &mut Vec<&'a mut ComputedVariable>,
+        ) {
+            all.extend(connections.iter_mut().flat_map(|c| &mut c.computed));
+            for branch in unions.iter_mut().flat_map(|u| &mut u.branches) {
+                let UnionBranch {
+                    connections,
+                    unions,
+                    computed,
+                    ..
+                } = branch;
+                all.extend(computed);
+                collect(connections, unions, all);
+            }
+        }
+
+        let mut all: Vec<&mut ComputedVariable> = self.computed.iter_mut().collect();
+        collect(&mut self.connections, &mut self.unions, &mut all);
+        all
+    }
+
+    /// All properties of the graph, its template and its subqueries.
+    fn all_properties_mut(&mut self) -> Vec<&mut Property> {
+        fn collect<'a>(
+            connections: &'a mut [Connection],
+            unions: &'a mut [UnionGroup],
+            all: &mut Vec<&'a mut Property>,
+        ) {
+            all.extend(connections.iter_mut().flat_map(|c| c.properties.iter_mut()));
+            for branch in unions.iter_mut().flat_map(|u| &mut u.branches) {
+                collect(&mut branch.connections, &mut branch.unions, all);
+            }
+        }
+
+        let mut all = Vec::new();
+        collect(&mut self.connections, &mut self.unions, &mut all);
+        collect(&mut self.template, &mut [], &mut all);
+        for subquery in &mut self.subqueries {
+            all.extend(subquery.graph.all_properties_mut());
+        }
+        all
+    }
+
+    /// The first occurrence of the entity with the given id.
+    fn find_entity_mut(&mut self, id: &str) -> Option<&mut Entity> {
+        self.all_connections_mut()
+            .into_iter()
+            .flat_map(|c| c.entities_mut())
+            .find(|entity| entity.id == id)
+    }
+}
+
+/// Alternative subgraphs of which at least one has to match.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnionGroup {
+    pub branches: Vec<UnionBranch>,
+}
+
+/// One alternative of a `UnionGroup`.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnionBranch {
+    pub connections: Vec<Connection>,
+    /// Filters of this branch which are not attached to an entity.
+    #[serde(default)]
+    pub filters: Vec<Expression>,
+    /// `BIND`s of this branch.
+    #[serde(default)]
+    pub computed: Vec<ComputedVariable>,
+    /// Nested alternatives inside this branch.
+    #[serde(default)]
+    pub unions: Vec<UnionGroup>,
+}
+
+/// The JSON accepted by `vqg_to_query_wasm`: either a complete graph or, as
+/// before, only the list of connections.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VqgInput {
+    Graph(Box<QueryGraph>),
+    Connections(Vec<Connection>),
+}
+
+impl From<VqgInput> for QueryGraph {
+    fn from(input: VqgInput) -> Self {
+        match input {
+            VqgInput::Graph(graph) => *graph,
+            VqgInput::Connections(connections) => QueryGraph {
+                connections,
+                ..QueryGraph::default()
+            },
+        }
+    }
+}
+
+// wasm method, to get a string containing a JSON, which converts it to a
+// QueryGraph and then calls vqg_to_query
 #[wasm_bindgen]
 pub fn vqg_to_query_wasm(
     json: &str,
@@ -71,14 +693,15 @@ pub fn vqg_to_query_wasm(
     // for better errors logging in the web browser
     set_panic_hook();
 
-    let connections: Vec<Connection> = from_str(json).unwrap_or_else(|_| vec![]);
-    vqg_to_query(connections, add_label_service, add_label_service_prefixes)
+    let graph: QueryGraph = from_str::<VqgInput>(json)
+        .map(QueryGraph::from)
+        .unwrap_or_default();
+    vqg_to_query(graph, add_label_service, add_label_service_prefixes)
 }
 
 fn get_iri(id: &str, prefix: &Prefix) -> String {
-    if prefix.iri.is_empty() {
-        id.to_string()
-    } else if id.contains(':') || id.starts_with('<') {
+    // ids without a prefix, variables, already prefixed ids and full IRIs are used as they are
+    if prefix.iri.is_empty() || id.starts_with('?') || id.contains(':') || id.starts_with('<') {
         id.to_string()
     } else {
         format!("{}:{}", prefix.abbreviation, id)
@@ -98,7 +721,7 @@ fn generate_property_path(property: &Property) -> String {
         let parts: Vec<String> = property
             .properties
             .iter()
-            .map(|p| generate_property_path(p))
+            .map(generate_property_path)
             .collect();
         let separator = match property.path_type.as_deref() {
             Some("alternation") => "|",
@@ -112,108 +735,877 @@ fn generate_property_path(property: &Property) -> String {
             format!("({})", path)
         }
     }
-}
+}
+
+fn render_triple(connection: &Connection, property: &Property, indentation: &str) -> String {
+    if connection.target.snak_type == Some(SnakType::NoValue) && !wikibase::is_statement(property) {
+        if let Some(no_value) = wikibase::render_no_value(connection, property, indentation) {
+            return no_value;
+        }
+    }
+    if wikibase::is_statement(property) {
+        if let Some(statement) = wikibase::render_statement(connection, property, indentation) {
+            return statement;
+        }
+    }
+    format!(
+        "{}{} {} {} .\n{}# {} -- [{}] -> {}\n",
+        indentation,
+        get_iri(&connection.source.id, &connection.source.prefix),
+        generate_property_path(property),
+        get_iri(&connection.target.id, &connection.target.prefix),
+        indentation,
+        connection.source.label,
+        property.label,
+        connection.target.label
+    )
+}
+
+fn render_block(keyword: &str, body: &str, indentation: &str) -> String {
+    format!("{}{} {{\n{}{}}}\n", indentation, keyword, body, indentation)
+}
+
+/// Renders the content of a group graph pattern at the given nesting depth.
+///
+/// `VALUES` blocks and mandatory triples come first, followed by the `UNION`
+/// groups, the `OPTIONAL` blocks and the negated blocks, each in the order of
+/// the connections.
+/// Optional or negated connections sharing a group are merged into the block of
+/// the first connection of that group. The filters of a connection and of its
+/// entities are placed into the block of the connection, or after all triples
+/// next to the given filters if the connection is mandatory. The `VALUES` of
+/// an entity open the block of the connection carrying them.
+fn render_group(
+    connections: &[Connection],
+    unions: &[UnionGroup],
+    filters: &[Expression],
+    computed: &[ComputedVariable],
+    depth: usize,
+) -> String {
+    let indentation = " ".repeat(INDENTATION_COUNT * depth);
+    let inner_indentation = " ".repeat(INDENTATION_COUNT * (depth + 1));
+
+    let mut mandatory: Vec<(&Connection, &Property)> = Vec::new();
+    // (keyword, triples) of the OPTIONAL, FILTER NOT EXISTS and MINUS blocks
+    let mut blocks: Vec<(&str, Vec<(&Connection, &Property)>)> = Vec::new();
+    let mut group_blocks: HashMap<(&str, &str), usize> = HashMap::new();
+    // the block each connection is rendered in, `None` for the group itself
+    let mut scopes: Vec<(&Connection, Option<usize>)> = Vec::new();
+
+    for connection in connections {
+        let keyword = match (&connection.negation, connection.optional) {
+            (Some(negation), _) => negation.keyword(),
+            (None, true) => "OPTIONAL",
+            (None, false) => {
+                for property in &connection.properties {
+                    if property.optional {
+                        blocks.push(("OPTIONAL", vec![(connection, property)]));
+                    } else {
+                        mandatory.push((connection, property));
+                    }
+                }
+                // a single optional property makes up the block of the connection
+                let scope = match connection.properties.as_slice() {
+                    [property] if property.optional => Some(blocks.len() - 1),
+                    _ => None,
+                };
+                scopes.push((connection, scope));
+                continue;
+            }
+        };
+
+        let triples = connection.properties.iter().map(|p| (connection, p));
+        let index = match &connection.group {
+            Some(group) => match group_blocks.get(&(keyword, group.as_str())) {
+                Some(&index) => {
+                    blocks[index].1.extend(triples);
+                    index
+                }
+                None => {
+                    group_blocks.insert((keyword, group), blocks.len());
+                    blocks.push((keyword, triples.collect()));
+                    blocks.len() - 1
+                }
+            },
+            None => {
+                blocks.push((keyword, triples.collect()));
+                blocks.len() - 1
+            }
+        };
+        scopes.push((connection, Some(index)));
+    }
+    // negations come last, as MINUS only removes what has been matched before
+    let mut order: Vec<usize> = (0..blocks.len()).collect();
+    order.sort_by_key(|&index| blocks[index].0 != "OPTIONAL");
+
+    let mut rendered_filters = String::new();
+    let mut block_filters = vec![String::new(); blocks.len()];
+    for (filter, scope) in collect_scoped_filters(&scopes) {
+        match scope {
+            Some(index) => block_filters[index].push_str(&render_filter(&filter, &inner_indentation)),
+            None => rendered_filters.push_str(&render_filter(&filter, &indentation)),
+        }
+    }
+    for filter in filters {
+        rendered_filters.push_str(&render_filter(filter, &indentation));
+    }
+    // the rank filters of statements, which their triples leave out
+    for (_, property) in &mandatory {
+        if let Some(filter) = wikibase::rank_filter(property) {
+            rendered_filters.push_str(&render_filter(&filter, &indentation));
+        }
+    }
+    for (index, (_, triples)) in blocks.iter().enumerate() {
+        for (_, property) in triples {
+            if let Some(filter) = wikibase::rank_filter(property) {
+                block_filters[index].push_str(&render_filter(&filter, &inner_indentation));
+            }
+        }
+    }
+
+    let mut group_computed: Vec<&ComputedVariable> = computed.iter().collect();
+    let mut block_computed: Vec<Vec<&ComputedVariable>> = vec![Vec::new(); blocks.len()];
+    for (connection, scope) in &scopes {
+        match scope {
+            Some(index) => block_computed[*index].extend(&connection.computed),
+            None => group_computed.extend(&connection.computed),
+        }
+    }
+    let (leading_binds, trailing_binds) = render_binds(&group_computed, &indentation);
+
+    let mut result = String::new();
+    let mut block_values = vec![String::new(); blocks.len()];
+    for (entity, values, scope) in collect_scoped_values(&scopes) {
+        match scope {
+            Some(index) => block_values[index].push_str(&render_values(entity, &values, &inner_indentation)),
+            None => result.push_str(&render_values(entity, &values, &indentation)),
+        }
+    }
+    result.push_str(&leading_binds);
+    for (c, p) in &mandatory {
+        result.push_str(&render_triple(c, p, &indentation));
+    }
+    for union in unions {
+        result.push_str(&render_union(union, depth));
+    }
+    for index in order {
+        let (keyword, triples) = &blocks[index];
+        let (leading, trailing) = render_binds(&block_computed[index], &inner_indentation);
+        let triples: String = triples
+            .iter()
+            .map(|(c, p)| render_triple(c, p, &inner_indentation))
+            .collect();
+        let body = block_values[index].clone() + &leading + &triples + &trailing + &block_filters[index];
+        result.push_str(&render_block(keyword, &body, &indentation));
+    }
+    result + &trailing_binds + &rendered_filters
+}
+
+/// Renders the `BIND`s of a group, split into those of a constant, which
+/// come first, and the others, which follow the triples whose variables they
+/// use.
+fn render_binds(computed: &[&ComputedVariable], indentation: &str) -> (String, String) {
+    let (mut leading, mut trailing) = (String::new(), String::new());
+    for computed in computed.iter().filter(|computed| computed.bind) {
+        let bind = format!(
+            "{}BIND{}\n",
+            indentation,
+            render_alias(&computed.expression, &computed.alias)
+        );
+        if expression_variables(&computed.expression).is_empty() {
+            leading.push_str(&bind);
+        } else {
+            trailing.push_str(&bind);
+        }
+    }
+    (leading, trailing)
+}
+
+fn render_union(union: &UnionGroup, depth: usize) -> String {
+    let indentation = " ".repeat(INDENTATION_COUNT * depth);
+    let branches = union
+        .branches
+        .iter()
+        .map(|branch| {
+            format!(
+                "{{\n{}{}}}",
+                render_group(
+                    &branch.connections,
+                    &branch.unions,
+                    &branch.filters,
+                    &branch.computed,
+                    depth + 1
+                ),
+                indentation
+            )
+        })
+        .collect::<Vec<_>>();
+    if branches.is_empty() {
+        String::new()
+    } else {
+        format!("{}{}\n", indentation, branches.join(" UNION "))
+    }
+}
+
+/// Collects the filters of all connections and their entities with the block
+/// they are rendered in. Entity filters are deduplicated per entity id, since
+/// every connection carries its own copy of an entity; a filter carried in
+/// several scopes is rendered in the group itself.
+fn collect_scoped_filters(scopes: &[(&Connection, Option<usize>)]) -> Vec<(Expression, Option<usize>)> {
+    fn add(filters: &mut Vec<(String, Expression, Option<usize>)>, id: &str, filter: &Expression, scope: Option<usize>) {
+        match filters.iter_mut().find(|(i, f, _)| i == id && f == filter) {
+            Some((_, _, existing)) if *existing != scope => *existing = None,
+            Some(_) => {}
+            None => filters.push((id.to_string(), filter.clone(), scope)),
+        }
+    }
+
+    let mut filters = Vec::new();
+    for &(connection, scope) in scopes {
+        for entity in connection.entities() {
+            if entity.snak_type == Some(SnakType::SomeValue) {
+                add(&mut filters, &entity.id, &wikibase::some_value_filter(&entity.id), scope);
+            }
+            for filter in &entity.filters {
+                add(&mut filters, &entity.id, filter, scope);
+            }
+        }
+        for filter in &connection.filters {
+            add(&mut filters, "", filter, scope);
+        }
+    }
+    filters.into_iter().map(|(_, filter, scope)| (filter, scope)).collect()
+}
+
+/// Collects the rendered candidate values of every variable entity, once per
+/// entity id, with the block they are rendered in. Values carried in several
+/// scopes are rendered in the group itself.
+fn collect_scoped_values<'a>(
+    scopes: &[(&'a Connection, Option<usize>)],
+) -> Vec<(&'a Entity, Vec<String>, Option<usize>)> {
+    let mut values: Vec<(&Entity, Vec<String>, Option<usize>)> = Vec::new();
+    for &(connection, scope) in scopes {
+        for entity in connection.entities() {
+            if !entity.id.starts_with('?') || entity.values.is_empty() {
+                continue;
+            }
+            match values.iter_mut().find(|(e, _, _)| e.id == entity.id) {
+                Some((_, _, existing)) if *existing != scope => *existing = None,
+                Some(_) => {}
+                None => {
+                    let rendered = entity
+                        .values
+                        .iter()
+                        .map(|value| {
+                            if is_plain_value(value) {
+                                value.clone()
+                            } else {
+                                get_iri(value, &entity.prefix)
+                            }
+                        })
+                        .collect();
+                    values.push((entity, rendered, scope));
+                }
+            }
+        }
+    }
+    values
+}
+
+fn render_values(entity: &Entity, values: &[String], indentation: &str) -> String {
+    format!("{}VALUES {} {{ {} }}\n", indentation, entity.id, values.join(" "))
+}
+
+/// Whether a candidate value is written as it is instead of being prefixed,
+/// i.e. a literal like `"foo"@en` or `42`, or `UNDEF`.
+fn is_plain_value(value: &str) -> bool {
+    value.starts_with(['"', '\''])
+        || value.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'))
+        || matches!(value, "true" | "false" | "UNDEF")
+}
+
+fn render_filter(filter: &Expression, indentation: &str) -> String {
+    format!("{}FILTER({})\n", indentation, render_expression(filter))
+}
+
+/// Collects the aggregates of all entities with the id of their entity,
+/// deduplicated by alias.
+fn collect_aggregates(connections: &[&Connection]) -> Vec<(String, Aggregate)> {
+    let mut aggregates: Vec<(String, Aggregate)> = Vec::new();
+    for connection in connections {
+        for entity in connection.entities() {
+            for aggregate in &entity.aggregates {
+                if !aggregates.iter().any(|(_, a)| a.alias == aggregate.alias) {
+                    aggregates.push((entity.id.clone(), aggregate.clone()));
+                }
+            }
+        }
+    }
+    aggregates
+}
+
+/// Renders e.g. `(COUNT(DISTINCT ?student) AS ?students)`.
+fn render_aggregate_projection(id: &str, aggregate: &Aggregate) -> String {
+    let expression = Expression::Aggregate {
+        function: aggregate.function,
+        distinct: aggregate.distinct,
+        argument: Some(Box::new(Expression::Variable { name: id.to_string() })),
+        separator: aggregate.separator.clone(),
+    };
+    render_alias(&expression, &aggregate.alias)
+}
+
+/// Renders `GROUP BY`, `HAVING`, `ORDER BY`, `LIMIT` and `OFFSET`, each on its
+/// own line.
+fn render_solution_modifiers(settings: &QuerySettings, group_by: &[String], indentation: &str) -> String {
+    let mut modifiers = String::new();
+    if !group_by.is_empty() {
+        modifiers.push_str(&format!("\n{}GROUP BY {}", indentation, group_by.join(" ")));
+    }
+    if !settings.having.is_empty() {
+        let conditions = settings
+            .having
+            .iter()
+            .map(|condition| format!("({})", render_expression(condition)))
+            .collect::<Vec<_>>();
+        modifiers.push_str(&format!("\n{}HAVING {}", indentation, conditions.join(" ")));
+    }
+    if !settings.order_by.is_empty() {
+        let keys = settings
+            .order_by
+            .iter()
+            .map(|key| {
+                let variable = if key.variable.starts_with('?') {
+                    key.variable.clone()
+                } else {
+                    format!("?{}", key.variable)
+                };
+                if key.descending {
+                    format!("DESC({})", variable)
+                } else {
+                    variable
+                }
+            })
+            .collect::<Vec<_>>();
+        modifiers.push_str(&format!("\n{}ORDER BY {}", indentation, keys.join(" ")));
+    }
+    if let Some(limit) = settings.limit {
+        modifiers.push_str(&format!("\n{}LIMIT {}", indentation, limit));
+    }
+    if let Some(offset) = settings.offset {
+        modifiers.push_str(&format!("\n{}OFFSET {}", indentation, offset));
+    }
+    modifiers
+}
+
+/// What a graph projects: the plain variables with their distinct flag, sorted
+/// by name, the aggregates with the id of their entity and the projection
+/// expressions.
+struct Projection<'a> {
+    variables: Vec<(String, bool)>,
+    aggregates: Vec<(String, Aggregate)>,
+    expressions: Vec<&'a ComputedVariable>,
+}
+
+impl Projection<'_> {
+    /// The names of all projected variables, including aliases.
+    fn names(&self) -> Vec<String> {
+        self.variables
+            .iter()
+            .map(|(var, _)| var.clone())
+            .chain(self.aggregates.iter().map(|(_, aggregate)| aggregate.alias.clone()))
+            .chain(self.expressions.iter().map(|computed| computed.alias.clone()))
+            .collect()
+    }
+}
+
+fn collect_vars(
+    id: &str,
+    selected: bool,
+    distinct: bool,
+    add_service_statement: bool,
+    companions: Companions,
+) -> Vec<(String, bool)> {
+    let mut vars = Vec::new();
+    if id.starts_with('?') && selected {
+        let var = id.to_string();
+        vars.push((var.clone(), distinct));
+        if add_service_statement {
+            // Label variables are never marked distinct
+            for suffix in companions.suffixes() {
+                vars.push((format!("{}{}", var, suffix), false));
+            }
+        }
+    }
+    vars
+}
+
+fn collect_vars_from_property(property: &Property, add_service_statement: bool) -> Vec<(String, bool)> {
+    // Properties never carry the distinct flag
+    let mut vars = collect_vars(
+        &property.id,
+        property.selected_for_projection,
+        false,
+        add_service_statement,
+        Companions::default(),
+    );
+    if let Some(statement) = property.statement.as_deref() {
+        vars.extend(collect_vars(
+            statement,
+            property.statement_selected_for_projection,
+            false,
+            false,
+            Companions::default(),
+        ));
+    }
+    for p in &property.properties {
+        vars.extend(collect_vars_from_property(p, add_service_statement));
+    }
+    vars
+}
+
+fn collect_prefixes_from_property(property: &Property) -> Vec<Prefix> {
+    let mut prefixes = Vec::new();
+    let statement_prefixes = if wikibase::is_statement(property) {
+        wikibase::statement_prefixes(property)
+    } else {
+        vec![]
+    };
+    if !statement_prefixes.is_empty() {
+        prefixes.extend(statement_prefixes);
+    } else if !property.prefix.iri.is_empty() {
+        prefixes.push(property.prefix.clone());
+    }
+    for p in &property.properties {
+        prefixes.extend(collect_prefixes_from_property(p));
+    }
+    prefixes
+}
+
+/// Collects the prefixes of a graph, its template and all of its subqueries.
+fn collect_prefixes(graph: &QueryGraph) -> HashSet<Prefix> {
+    let mut prefixes: HashSet<Prefix> = graph
+        .all_connections()
+        .into_iter()
+        .chain(&graph.template)
+        .flat_map(|connection| {
+            let mut prefixes = Vec::new();
+            for entity in connection.entities() {
+                if !entity.prefix.iri.is_empty() {
+                    prefixes.push(entity.prefix.clone());
+                }
+            }
+            for property in &connection.properties {
+                if connection.target.snak_type == Some(SnakType::NoValue) {
+                    if let Some(no_value) = wikibase::no_value_prefixes(property) {
+                        prefixes.extend(no_value);
+                        continue;
+                    }
+                }
+                prefixes.extend(collect_prefixes_from_property(property));
+            }
+            prefixes
+        })
+        .collect();
+    for subquery in &graph.subqueries {
+        prefixes.extend(collect_prefixes(&subquery.graph));
+    }
+    prefixes
+}
+
+fn projection(graph: &QueryGraph, add_service_statement: bool) -> Projection<'_> {
+    let connections = graph.all_connections();
+
+    // Aggregated entities are projected through their aggregates only
+    let aggregates = collect_aggregates(&connections);
+    let aggregated: HashSet<&str> = aggregates.iter().map(|(id, _)| id.as_str()).collect();
+
+    // Collect (variable, is_distinct) pairs; deduplicate with OR on the distinct flag
+    let projection_raw: Vec<(String, bool)> = connections
+        .iter()
+        .flat_map(|connection| {
+            let mut vars: Vec<(String, bool)> = Vec::new();
+            for entity in connection.entities() {
+                let selected = entity.selected_for_projection
+                    && !aggregated.contains(entity.id.as_str())
+                    && entity.snak_type != Some(SnakType::NoValue);
+                vars.extend(collect_vars(
+                    &entity.id,
+                    selected,
+                    entity.distinct,
+                    add_service_statement,
+                    entity.companions,
+                ));
+            }
+            for property in &connection.properties {
+                vars.extend(collect_vars_from_property(property, add_service_statement));
+            }
+            vars
+        })
+        .collect();
+
+    let bound = graph
+        .all_computed()
+        .into_iter()
+        .filter(|computed| computed.bind && computed.selected_for_projection)
+        .map(|computed| (render_expression(&Expression::Variable { name: computed.alias.clone() }), false));
+
+    // Variables projected by a subquery are projected by the enclosing query
+    // as well, only plain variables get a label
+    let from_subqueries: Vec<(String, bool)> = graph
+        .subqueries
+        .iter()
+        .flat_map(|subquery| {
+            let inner = projection(&subquery.graph, false);
+            let mut vars: Vec<(String, bool)> = inner
+                .variables
+                .iter()
+                .flat_map(|(var, _)| collect_vars(var, true, false, add_service_statement, Companions::default()))
+                .collect();
+            let plain = inner.variables.len();
+            vars.extend(inner.names().into_iter().skip(plain).map(|alias| (alias, false)));
+            vars
+        })
+        .collect();
+
+    let mut projection_map: HashMap<String, bool> = HashMap::new();
+    for (var, is_distinct) in projection_raw.into_iter().chain(bound).chain(from_subqueries) {
+        projection_map
+            .entry(var)
+            .and_modify(|d| *d = *d || is_distinct)
+            .or_insert(is_distinct);
+    }
+
+    let mut variables: Vec<(String, bool)> = projection_map.into_iter().collect();
+    variables.sort_by_key(|(var, _)| var.clone());
+
+    Projection {
+        variables,
+        aggregates,
+        expressions: graph
+            .computed
+            .iter()
+            .filter(|computed| !computed.bind && computed.selected_for_projection)
+            .collect(),
+    }
+}
+
+/// Renders the content of the WHERE clause of a graph at the given nesting
+/// depth: its subqueries and its group with the `BIND`s of its computed
+/// variables.
+fn render_where(graph: &QueryGraph, depth: usize) -> String {
+    let mut where_clause: String = graph
+        .subqueries
+        .iter()
+        .map(|subquery| render_subquery(subquery, depth))
+        .collect();
+    where_clause.push_str(&render_group(
+        &graph.connections,
+        &graph.unions,
+        &graph.filters,
+        &graph.computed,
+        depth,
+    ));
+    where_clause
+}
+
+/// Renders the `ASK` query of a graph without prefixes. There is no
+/// projection, so neither the label service nor a `GROUP BY` is needed.
+fn render_ask(graph: &QueryGraph) -> String {
+    format!(
+        "ASK WHERE {{\n{}}}{}",
+        render_where(graph, 1),
+        render_solution_modifiers(&graph.settings, &[], "")
+    )
+}
+
+/// Renders the `CONSTRUCT` query of a graph without prefixes. Without a
+/// template, every connection which is not negated is constructed. Property
+/// paths cannot be constructed and are left out.
+fn render_construct(graph: &QueryGraph) -> String {
+    let indentation = " ".repeat(INDENTATION_COUNT);
+    let template: Vec<&Connection> = if graph.template.is_empty() {
+        graph
+            .all_connections()
+            .into_iter()
+            .filter(|connection| connection.negation.is_none())
+            .collect()
+    } else {
+        graph.template.iter().collect()
+    };
+    let mut triples = String::new();
+    for connection in template {
+        for property in &connection.properties {
+            if property.properties.is_empty() && property.modifier.is_none() {
+                triples.push_str(&render_triple(connection, property, &indentation));
+            }
+        }
+    }
+    format!(
+        "CONSTRUCT {{\n{}}} WHERE {{\n{}}}{}",
+        triples,
+        render_where(graph, 1),
+        render_solution_modifiers(&graph.settings, &[], "")
+    )
+}
+
+/// Renders the `DESCRIBE` query of a graph without prefixes, describing the
+/// variables a `SELECT` query would project.
+fn render_describe(graph: &QueryGraph) -> String {
+    let projection = projection(graph, false);
+    let described = if projection.variables.is_empty() {
+        String::from("*")
+    } else {
+        projection
+            .variables
+            .iter()
+            .map(|(var, _)| var.clone())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    format!(
+        "DESCRIBE {} WHERE {{\n{}}}{}",
+        described,
+        render_where(graph, 1),
+        render_solution_modifiers(&graph.settings, &[], "")
+    )
+}
+
+/// Renders the labels of the projected label variables as
+///
+/// ```sparql
+/// OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "de") }
+/// OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "en") }
+/// ```
+///
+/// where a later block only binds a label if no earlier one did. Description
+/// and alias variables are bound alike with `schema:description` and
+/// `skos:altLabel`; unlike the label service, which joins all aliases, this
+/// gives one row per alias.
+fn render_rdfs_labels(projection: &Projection, labels: &LabelSettings, indentation: &str) -> String {
+    let names: HashSet<&str> = projection.variables.iter().map(|(var, _)| var.as_str()).collect();
+    let mut result = String::new();
+    for (var, _) in &projection.variables {
+        for (suffix, abbreviation, _, local) in COMPANIONS.iter() {
+            let companion = format!("{}{}", var, suffix);
+            if !names.contains(companion.as_str()) {
+                continue;
+            }
+            for language in labels.label_languages() {
+                result.push_str(&format!(
+                    "{}OPTIONAL {{ {} {}:{} {} . FILTER(LANG({}) = \"{}\") }}\n",
+                    indentation, var, abbreviation, local, companion, companion, language
+                ));
+            }
+        }
+    }
+    result
+}
+
+/// Renders the `SELECT` query of a graph without prefixes, with the `SELECT`
+/// keyword at the given nesting depth.
+fn render_select(graph: &QueryGraph, add_service_statement: bool, depth: usize) -> String {
+    let indentation = " ".repeat(INDENTATION_COUNT * depth);
+    let inner_indentation = " ".repeat(INDENTATION_COUNT * (depth + 1));
+    let projection = projection(graph, add_service_statement);
+
+    // With aggregates and without explicit keys, all other projected
+    // variables are grouped. Explicit keys are completed by the projected
+    // variables missing from them, which could not be projected otherwise
+    let aggregating = !projection.aggregates.is_empty()
+        || projection
+            .expressions
+            .iter()
+            .any(|computed| contains_aggregate(&computed.expression));
+    let group_by: Vec<String> = if !graph.settings.group_by.is_empty() {
+        let mut keys = graph.settings.group_by.clone();
+        for (var, _) in &projection.variables {
+            if !keys.contains(var) {
+                keys.push(var.clone());
+            }
+        }
+        keys
+    } else if !aggregating {
+        vec![]
+    } else {
+        projection.variables.iter().map(|(var, _)| var.clone()).collect()
+    };
+
+    // SPARQL has no per-variable DISTINCT, so a distinct variable makes
+    // the whole result distinct
+    let distinct = if projection.variables.iter().any(|(_, is_distinct)| *is_distinct) {
+        "DISTINCT "
+    } else {
+        ""
+    };
+
+    let projection_list = if projection.names().is_empty() {
+        String::from("*")
+    } else {
+        projection
+            .variables
+            .iter()
+            .map(|(var, _)| var.clone())
+            .chain(
+                projection
+                    .aggregates
+                    .iter()
+                    .map(|(id, aggregate)| render_aggregate_projection(id, aggregate)),
+            )
+            .chain(
+                projection
+                    .expressions
+                    .iter()
+                    .map(|computed| render_alias(&computed.expression, &computed.alias)),
+            )
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let where_clause = render_where(graph, depth + 1);
+
+    let mut labels = graph.settings.labels.clone().unwrap_or_default();
+    if labels.languages.is_empty() {
+        if let Some(data_source) = &graph.settings.data_source {
+            labels.languages = wikibase::languages_of(data_source);
+        }
+    }
+    let service = if add_service_statement && labels.strategy == LabelStrategy::RdfsLabel {
+        render_rdfs_labels(&projection, &labels, &inner_indentation)
+    } else if add_service_statement {
+        format!(
+            "{}SERVICE wikibase:label {{ bd:serviceParam wikibase:language \"{}\". }}\n",
+            inner_indentation,
+            labels.language_list()
+        )
+    } else {
+        String::from("")
+    };
 
-fn vqg_to_query(
-    connections: Vec<Connection>,
-    add_service_statement: bool,
-    add_label_service_prefixes: bool,
-) -> String {
-    let indentation = " ".repeat(INDENTATION_COUNT);
+    let modifiers = render_solution_modifiers(&graph.settings, &group_by, &indentation);
 
-    if connections.is_empty() {
-        String::from("")
-    } else {
-        fn collect_vars(id: &str, selected: bool, distinct: bool, add_service_statement: bool) -> Vec<(String, bool)> {
-            let mut vars = Vec::new();
-            if id.starts_with('?') && selected {
-                let var = id.to_string();
-                vars.push((var.clone(), distinct));
-                if add_service_statement {
-                    // Label variables are never marked distinct
-                    vars.push((format!("?{}Label", var.trim_start_matches('?')), false));
-                }
-            }
-            vars
-        }
+    format!(
+        "{}SELECT {}{} WHERE {{\n{}{}{}}}{}",
+        indentation, distinct, projection_list, where_clause, service, indentation, modifiers
+    )
+}
 
-        fn collect_vars_from_property(property: &Property, add_service_statement: bool) -> Vec<(String, bool)> {
-            // Properties never carry the distinct flag
-            let mut vars = collect_vars(&property.id, property.selected_for_projection, false, add_service_statement);
-            for p in &property.properties {
-                vars.extend(collect_vars_from_property(p, add_service_statement));
-            }
-            vars
-        }
+/// Renders `{ SELECT ... }` at the given nesting depth.
+fn render_subquery(subquery: &SubQuery, depth: usize) -> String {
+    let indentation = " ".repeat(INDENTATION_COUNT * depth);
+    format!(
+        "{}{{\n{}\n{}}}\n",
+        indentation,
+        render_select(&subquery.graph, false, depth + 1),
+        indentation
+    )
+}
 
-        fn collect_prefixes_from_property(property: &Property) -> Vec<Prefix> {
-            let mut prefixes = Vec::new();
-            if !property.prefix.iri.is_empty() {
-                prefixes.push(property.prefix.clone());
-            }
-            for p in &property.properties {
-                prefixes.extend(collect_prefixes_from_property(p));
+/// Applies the statement mode of the graph, then names the statement nodes of
+/// qualified, referenced or ranked edges without a statement variable
+/// `?statement1`, `?statement2`, ..., their reference nodes without a
+/// variable `?reference1`, `?reference2`, ... and their value nodes without a
+/// variable `?value1`, `?value2`, ..., skipping names which are taken.
+fn assign_statement_variables(graph: &mut QueryGraph) {
+    fn fresh(stem: &str, count: &mut usize, taken: &mut HashSet<String>) -> String {
+        loop {
+            *count += 1;
+            let name = format!("?{}{}", stem, count);
+            if taken.insert(name.clone()) {
+                break name;
             }
-            prefixes
         }
+    }
 
-        // Collect (variable, is_distinct) pairs; deduplicate with OR on the distinct flag
-        let projection_raw: Vec<(String, bool)> = connections
-            .iter()
-            .flat_map(|connection| {
-                let mut vars: Vec<(String, bool)> = Vec::new();
-                for entity in &[&connection.source, &connection.target] {
-                    vars.extend(collect_vars(&entity.id, entity.selected_for_projection, entity.distinct, add_service_statement));
-                }
-                for property in &connection.properties {
-                    vars.extend(collect_vars_from_property(property, add_service_statement));
-                }
-                vars
-            })
-            .collect();
+    let mode = graph.settings.statement_mode;
+    // statement variables which are used as entities, too
+    let used: HashSet<String> = graph
+        .all_connections()
+        .iter()
+        .flat_map(|c| c.entities())
+        .map(|entity| entity.id.clone())
+        .chain(graph.filters.iter().flat_map(expression_variables))
+        .chain(graph.all_connections().iter().flat_map(|c| &c.filters).flat_map(expression_variables))
+        .collect();
 
-        let mut projection_map: HashMap<String, bool> = HashMap::new();
-        for (var, is_distinct) in projection_raw {
-            projection_map
-                .entry(var)
-                .and_modify(|d| *d = *d || is_distinct)
-                .or_insert(is_distinct);
+    let mut properties = graph.all_properties_mut();
+    if mode == Some(StatementMode::Truthy) {
+        for property in properties.iter_mut() {
+            wikibase::to_truthy(property, &used);
         }
-
-        let projection_list = if projection_map.is_empty() {
-            String::from("*")
-        } else {
-            let mut sorted: Vec<(String, bool)> = projection_map.into_iter().collect();
-            sorted.sort_by_key(|(var, _)| var.clone());
-            sorted
+    }
+    let mut taken: HashSet<String> = properties
+        .iter()
+        .flat_map(|p| {
+            let value_nodes = p.value_node.iter().chain(p.qualifiers.iter().flat_map(|q| &q.value_node));
+            p.statement
                 .iter()
-                .map(|(var, is_distinct)| {
-                    if *is_distinct {
-                        format!("DISTINCT({})", var)
-                    } else {
-                        var.clone()
-                    }
-                })
-                .collect::<Vec<_>>()
-                .join(" ")
-        };
+                .chain(p.references.iter().flat_map(|r| &r.variable))
+                .chain(value_nodes.flat_map(|node| &node.variable))
+        })
+        .cloned()
+        .collect();
+    let (mut statements, mut references, mut values) = (0, 0, 0);
+    for property in properties.iter_mut() {
+        let needs_statement = wikibase::is_statement(property)
+            || (mode == Some(StatementMode::Statements) && wikibase::is_direct(property));
+        if property.statement.is_none() && needs_statement {
+            property.statement = Some(fresh("statement", &mut statements, &mut taken));
+        }
+        for reference in property.references.iter_mut().filter(|r| r.variable.is_none()) {
+            reference.variable = Some(fresh("reference", &mut references, &mut taken));
+        }
+        let value_nodes = property
+            .value_node
+            .iter_mut()
+            .chain(property.qualifiers.iter_mut().flat_map(|q| &mut q.value_node));
+        for value_node in value_nodes.filter(|node| node.variable.is_none()) {
+            value_node.variable = Some(fresh("value", &mut values, &mut taken));
+        }
+    }
+}
 
-        let prefix_set = connections
-            .iter()
-            .flat_map(|connection| {
-                let mut prefixes = Vec::new();
-                for entity in &[&connection.source, &connection.target] {
-                    if !entity.prefix.iri.is_empty() {
-                        prefixes.push(entity.prefix.clone());
-                    }
-                }
-                for property in &connection.properties {
-                    prefixes.extend(collect_prefixes_from_property(property));
-                }
-                prefixes
-            })
-            .collect::<HashSet<_>>();
+fn vqg_to_query(graph: QueryGraph, add_service_statement: bool, add_label_service_prefixes: bool) -> String {
+    let registry = WikibaseRegistry::with_custom(&graph.settings.wikibases);
+    wikibase::with_registry(registry, || {
+        render_query(graph, add_service_statement, add_label_service_prefixes)
+    })
+}
+
+fn render_query(
+    mut graph: QueryGraph,
+    add_service_statement: bool,
+    add_label_service_prefixes: bool,
+) -> String {
+    assign_statement_variables(&mut graph);
+    let add_service_statement = add_service_statement || graph.settings.labels.is_some();
+    if graph.all_connections().is_empty() && graph.subqueries.is_empty() {
+        String::from("")
+    } else {
+        let select = match graph.settings.form {
+            QueryForm::Select => render_select(&graph, add_service_statement, 0),
+            QueryForm::Ask => render_ask(&graph),
+            QueryForm::Construct => render_construct(&graph),
+            QueryForm::Describe => render_describe(&graph),
+        };
 
+        let mut prefix_set = collect_prefixes(&graph);
+        let label_strategy = graph.settings.labels.as_ref().map(|labels| labels.strategy);
+        let add_label_service_prefixes =
+            add_label_service_prefixes && label_strategy != Some(LabelStrategy::RdfsLabel);
+        for (_, abbreviation, iri, local) in COMPANIONS.iter() {
+            if select.contains(&format!(" {}:{} ", abbreviation, local)) {
+                prefix_set.insert(Prefix {
+                    iri: iri.to_string(),
+                    abbreviation: abbreviation.to_string(),
+                });
+            }
+        }
+        if add_label_service_prefixes {
+            // declared with the label service prefixes already
+            prefix_set.retain(|prefix| prefix.abbreviation != "wikibase");
+        }
+        let declares_xsd = prefix_set.iter().any(|prefix| prefix.abbreviation == "xsd");
         let prefix_list = if prefix_set.is_empty() {
             String::from("")
         } else {
@@ -225,60 +1617,21 @@ fn vqg_to_query(
             format!("{}\n\n", temp.join("\n"))
         };
 
-        // Detect whether any entity uses an ^^xsd: typed literal and inject the XSD prefix if so.
-        let uses_xsd = connections.iter().any(|c| {
-            c.source.id.contains("^^xsd:")
-                || c.target.id.contains("^^xsd:")
-                || c.properties.iter().any(|p| p.id.contains("^^xsd:"))
-        });
+        // Detect whether any term uses an ^^xsd: typed literal and inject the XSD prefix if so.
+        let uses_xsd = select.contains("^^xsd:") && !declares_xsd;
         let xsd_prefix = if uses_xsd {
             "PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>\n\n"
         } else {
             ""
         };
 
-        let where_clause: String = connections
-            .iter()
-            .map(|connection| {
-                let source_iri = get_iri(&connection.source.id, &connection.source.prefix);
-                let target_iri = get_iri(&connection.target.id, &connection.target.prefix);
-
-                connection.properties.iter().map(|property| {
-                    let property_path = generate_property_path(property);
-                    format!(
-                        "{}{} {} {} .\n{}# {} -- [{}] -> {}\n",
-                        indentation,
-                        source_iri,
-                        property_path,
-                        target_iri,
-                        indentation,
-                        connection.source.label,
-                        property.label,
-                        connection.target.label
-                    )
-                }).collect::<Vec<String>>().join("")
-            })
-            .collect();
-
-        let service = if add_service_statement {
-            format!(
-                "{}SERVICE wikibase:label {{ bd:serviceParam wikibase:language \"[AUTO_LANGUAGE],en\". }}\n",
-                indentation
-            )
-        } else {
-            String::from("")
-        };
-
         if add_label_service_prefixes {
             format!(
-                "{}\n{}\n{}{}SELECT {} WHERE {{\n{}{}}}",
-                BD_PREFIX, WIKIBASE_PREFIX, xsd_prefix, prefix_list, projection_list, where_clause, service
+                "{}\n{}\n{}{}{}",
+                BD_PREFIX, WIKIBASE_PREFIX, xsd_prefix, prefix_list, select
             )
         } else {
-            format!(
-                "{}{}SELECT {} WHERE {{\n{}{}}}",
-                xsd_prefix, prefix_list, projection_list, where_clause, service
-            )
+            format!("{}{}{}", xsd_prefix, prefix_list, select)
         }
     }
 }
@@ -308,6 +1661,16 @@ fn term_pattern_to_string(tp: &TermPattern) -> String {
     }
 }
 
+fn ground_term_to_string(term: &GroundTerm) -> String {
+    match term {
+        GroundTerm::NamedNode(n) => n.to_string(),
+        GroundTerm::Literal(l) => literal_to_string(l),
+        // RDF-star quoted triples
+        #[allow(unreachable_patterns)]
+        other => other.to_string(),
+    }
+}
+
 fn named_node_pattern_to_string(nnp: &NamedNodePattern) -> String {
     match nnp {
         NamedNodePattern::NamedNode(n) => n.to_string(),
@@ -328,6 +1691,13 @@ fn property_path_to_property(path: &PropertyPathExpression) -> Property {
             properties: vec![],
             path_type: None,
             modifier: None,
+            optional: false,
+            qualifiers: vec![],
+            statement: None,
+            statement_selected_for_projection: false,
+            references: vec![],
+            rank: None,
+            value_node: None,
         },
         PropertyPathExpression::Reverse(inner) => {
             let mut p = property_path_to_property(inner);
@@ -348,6 +1718,13 @@ fn property_path_to_property(path: &PropertyPathExpression) -> Property {
             ],
             path_type: Some("sequence".to_string()),
             modifier: None,
+            optional: false,
+            qualifiers: vec![],
+            statement: None,
+            statement_selected_for_projection: false,
+            references: vec![],
+            rank: None,
+            value_node: None,
         },
         PropertyPathExpression::Alternative(left, right) => Property {
             id: "alternation".to_string(),
@@ -363,6 +1740,13 @@ fn property_path_to_property(path: &PropertyPathExpression) -> Property {
             ],
             path_type: Some("alternation".to_string()),
             modifier: None,
+            optional: false,
+            qualifiers: vec![],
+            statement: None,
+            statement_selected_for_projection: false,
+            references: vec![],
+            rank: None,
+            value_node: None,
         },
         PropertyPathExpression::ZeroOrMore(inner) => {
             let mut p = property_path_to_property(inner);
@@ -392,6 +1776,13 @@ fn property_path_to_property(path: &PropertyPathExpression) -> Property {
                 properties: vec![],
                 path_type: None,
                 modifier: None,
+                optional: false,
+                qualifiers: vec![],
+                statement: None,
+                statement_selected_for_projection: false,
+                references: vec![],
+                rank: None,
+                value_node: None,
             }
         }
     }
@@ -402,7 +1793,28 @@ pub fn query_to_vqg_wasm(query: &str) -> String {
     // for better errors logging in the web browser
     set_panic_hook();
 
-    to_string(&query_to_vqg(query)).unwrap()
+    to_string(&query_to_vqg(query, vec![]).connections).unwrap()
+}
+
+/// Like `query_to_vqg_wasm`, but returns the complete `QueryGraph` including
+/// everything that is not part of a connection.
+#[wasm_bindgen]
+pub fn query_to_graph_wasm(query: &str) -> String {
+    // for better errors logging in the web browser
+    set_panic_hook();
+
+    to_string(&query_to_vqg(query, vec![])).unwrap()
+}
+
+/// Like `query_to_graph_wasm`, with custom Wikibase instances given as JSON
+/// list of `WikibaseFamily`, which are kept in the settings of the graph.
+#[wasm_bindgen]
+pub fn query_to_graph_with_wikibases_wasm(query: &str, wikibases: &str) -> String {
+    // for better errors logging in the web browser
+    set_panic_hook();
+
+    let wikibases: Vec<WikibaseFamily> = from_str(wikibases).unwrap_or_default();
+    to_string(&query_to_vqg(query, wikibases)).unwrap()
 }
 
 /// We get a query, can be a SELECT query or something else.
@@ -412,71 +1824,658 @@ pub fn query_to_vqg_wasm(query: &str) -> String {
 /// - base IRI (optional)
 ///
 /// The "graph pattern" is equivalent to a SPARQL Basic Graph Pattern (BGP)
-fn query_to_vqg(query: &str) -> Vec<Connection> {
+///
+/// IRIs are classified with the built-in and the given custom `wikibases`
+/// and compacted with the `PREFIX` declarations of the query and the
+/// namespaces of the Wikibase instances.
+fn query_to_vqg(query: &str, wikibases: Vec<WikibaseFamily>) -> QueryGraph {
+    let registry = WikibaseRegistry::with_custom(&wikibases);
+    let mut graph = wikibase::with_registry(registry, || {
+        let mut graph = parse_to_vqg(query);
+        prefixes::compact_graph(&mut graph, &prefixes::declared_prefixes(query));
+        graph
+    });
+    graph.settings.wikibases = wikibases;
+    graph
+}
+
+fn parse_to_vqg(query: &str) -> QueryGraph {
 
     if query.is_empty() {
-        return vec![];
+        return QueryGraph::default();
     }
 
-    fn _helper(parsed_query: Result<Query, SparqlSyntaxError>) -> Vec<Connection> {
+    fn _helper(parsed_query: Result<Query, SparqlSyntaxError>) -> QueryGraph {
         // Match on the query type.
         match parsed_query {
-            Ok(Query::Select { pattern: p, .. }) => {
-                let (connections, projection_vars) = match p {
-                    // SELECT DISTINCT ... is treated as SELECT ... for import purposes;
-                    // per-variable distinct is set by the user via the UI checkbox.
-                    GraphPattern::Distinct { inner } => match *inner {
-                        GraphPattern::Project { variables: v, inner: i } => (
-                            match_bgp_or_path_to_vqg(*i),
-                            Some(v.iter().map(|var| format!("?{}", var.as_str())).collect::<HashSet<String>>()),
-                        ),
-                        other => (match_bgp_or_path_to_vqg(other), None),
-                    },
-                    GraphPattern::Project {
-                        variables: v,
-                        inner: i,
-                    } => (
-                        match_bgp_or_path_to_vqg(*i),
-                        Some(v.iter().map(|var| format!("?{}", var.as_str())).collect::<HashSet<String>>()),
-                    ),
-                    _ => (match_bgp_or_path_to_vqg(p), None),
-                };
-
-                let mut connections = connections;
-                // Mark entities based on whether they're in the projection
-                if let Some(vars) = projection_vars {
-                    for connection in &mut connections {
-                        if connection.source.id.starts_with('?') {
-                            connection.source.selected_for_projection =
-                                vars.contains(&connection.source.id);
-                        }
-                        if connection.target.id.starts_with('?') {
-                            connection.target.selected_for_projection =
-                                vars.contains(&connection.target.id);
-                        }
-                        for property in &mut connection.properties {
-                            if property.id.starts_with('?') {
-                                property.selected_for_projection =
-                                    vars.contains(&property.id);
-                            }
-                        }
-                    }
+            Ok(Query::Select { pattern: p, .. }) => select_to_graph(p),
+            Ok(Query::Ask { pattern: p, .. }) => {
+                let mut graph = select_to_graph(p);
+                graph.settings.form = QueryForm::Ask;
+                graph
+            }
+            Ok(Query::Construct {
+                template,
+                pattern: p,
+                ..
+            }) => {
+                // a template repeating the pattern is the default, as in CONSTRUCT WHERE { ... }
+                let repeats_pattern = matches!(&p, GraphPattern::Bgp { patterns } if *patterns == template);
+                let mut graph = select_to_graph(p);
+                graph.settings.form = QueryForm::Construct;
+                if !repeats_pattern {
+                    graph.template = bgp_to_vqg(template);
                 }
-                connections
-            },
-            _ => vec![],
+                graph
+            }
+            Ok(Query::Describe { pattern: p, .. }) => {
+                let mut graph = select_to_graph(p);
+                // described IRIs are bound to internal variables and cannot be
+                // represented, everything else computed is a BIND
+                if graph
+                    .computed
+                    .iter()
+                    .any(|computed| !computed.bind && matches!(computed.expression, Expression::Term { .. }))
+                {
+                    return QueryGraph::default();
+                }
+                graph.settings.form = QueryForm::Describe;
+                for computed in &mut graph.computed {
+                    computed.bind = true;
+                }
+                graph
+            }
+            _ => QueryGraph::default(),
         }
     }
     let parsed_query = parse_query(query);
     match parsed_query {
         Err(_error) if !query.starts_with(WIKIBASE_PREFIX) && !query.starts_with(BD_PREFIX) => {
             let new_query = format!("{}{}{}", WIKIBASE_PREFIX, BD_PREFIX, query);
-            query_to_vqg(&new_query)
+            parse_to_vqg(&new_query)
         }
         _ => _helper(parsed_query),
     }
 }
 
+/// Builds the graph of a query from its pattern, an empty graph if the
+/// pattern cannot be represented.
+fn select_to_graph(pattern: GraphPattern) -> QueryGraph {
+    try_select_to_graph(pattern).unwrap_or_default()
+}
+
+/// Builds the graph of a query, or a subquery, from its pattern.
+fn try_select_to_graph(pattern: GraphPattern) -> Option<QueryGraph> {
+    let mut settings = QuerySettings::default();
+    let (pattern, projection_vars, distinct) = unwrap_solution_modifiers(pattern, &mut settings)?;
+    let mut context = ImportContext {
+        projection: projection_vars.clone(),
+        ..ImportContext::default()
+    };
+    let (pattern, aggregates, computed) =
+        unwrap_aggregation(pattern, &mut settings, projection_vars.as_ref())?;
+    let connections = match_bgp_or_path_to_vqg(pattern, &mut context);
+    if context.unsupported {
+        return None;
+    }
+
+    let mut graph = QueryGraph {
+        connections,
+        filters: std::mem::take(&mut context.filters),
+        unions: std::mem::take(&mut context.unions),
+        computed: computed.into_iter().chain(context.computed).collect(),
+        subqueries: context.subqueries,
+        template: vec![],
+        settings: QuerySettings {
+            labels: context.labels,
+            ..settings
+        },
+    };
+    // the values of a folded statement node cannot be kept
+    let valued = valued_variables(&graph);
+    wikibase::fold_statements(&mut graph);
+    if valued_variables(&graph) != valued {
+        return None;
+    }
+    for (id, aggregate) in aggregates {
+        if let Some(entity) = graph.find_entity_mut(&id) {
+            entity.aggregates.push(aggregate);
+        }
+    }
+    // values of a variable without an entity in the group cannot be kept
+    if !attach_values(context.values, &mut graph.connections, in_group_scope) {
+        return None;
+    }
+    // Mark entities based on whether they're in the projection;
+    // SELECT DISTINCT marks every projected entity distinct, and the
+    // projected label variables give the companions
+    let labelled = graph.settings.labels.is_some();
+    if let Some(vars) = projection_vars {
+        for computed in graph.all_computed_mut() {
+            computed.selected_for_projection = vars.contains(&computed.alias);
+        }
+        for connection in graph.all_connections_mut() {
+            for entity in connection.entities_mut() {
+                if entity.id.starts_with('?') {
+                    entity.selected_for_projection = vars.contains(&entity.id);
+                    entity.distinct = distinct && entity.selected_for_projection;
+                    if labelled && entity.selected_for_projection {
+                        let projected = |suffix: &str| vars.contains(&format!("{}{}", entity.id, suffix));
+                        entity.companions = Companions {
+                            label: projected("Label"),
+                            description: projected("Description"),
+                            alt_label: projected("AltLabel"),
+                        };
+                    }
+                }
+            }
+            for property in &mut connection.properties {
+                if property.id.starts_with('?') {
+                    property.selected_for_projection =
+                        vars.contains(&property.id);
+                }
+                property.statement_selected_for_projection =
+                    property.statement.as_ref().is_some_and(|statement| vars.contains(statement));
+            }
+        }
+    }
+    Some(graph)
+}
+
+/// Peels the solution modifiers off the pattern of a SELECT query, i.e.
+/// `Slice(Distinct(Project(OrderBy(inner))))` with every layer being optional.
+/// Returns the inner pattern, the projected variables, if any, and whether
+/// the result is distinct, or `None` if an order key is no plain variable.
+fn unwrap_solution_modifiers(
+    pattern: GraphPattern,
+    settings: &mut QuerySettings,
+) -> Option<(GraphPattern, Option<HashSet<String>>, bool)> {
+    let pattern = match pattern {
+        GraphPattern::Slice {
+            inner,
+            start,
+            length,
+        } => {
+            settings.offset = if start > 0 { Some(start) } else { None };
+            settings.limit = length;
+            *inner
+        }
+        other => other,
+    };
+    let (pattern, distinct) = match pattern {
+        GraphPattern::Distinct { inner } => (*inner, true),
+        // REDUCED only permits eliminating duplicates, so it is dropped
+        GraphPattern::Reduced { inner } => (*inner, false),
+        other => (other, false),
+    };
+    let (pattern, projection_vars) = match pattern {
+        GraphPattern::Project { inner, variables } => (
+            *inner,
+            Some(variables.iter().map(|var| format!("?{}", var.as_str())).collect()),
+        ),
+        other => (other, None),
+    };
+    let pattern = match pattern {
+        GraphPattern::OrderBy { inner, expression } => {
+            // only plain variables can be represented as order keys
+            settings.order_by = expression
+                .iter()
+                .map(|order| match order {
+                    OrderExpression::Asc(AlgebraExpression::Variable(v)) => Some(OrderKey {
+                        variable: format!("?{}", v.as_str()),
+                        descending: false,
+                    }),
+                    OrderExpression::Desc(AlgebraExpression::Variable(v)) => Some(OrderKey {
+                        variable: format!("?{}", v.as_str()),
+                        descending: true,
+                    }),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?;
+            *inner
+        }
+        other => other,
+    };
+    Some((pattern, projection_vars, distinct))
+}
+
+/// Whether a connection is rendered in its group itself rather than in an
+/// `OPTIONAL` or negated block.
+fn in_group_scope(connection: &Connection) -> bool {
+    connection.negation.is_none()
+        && !connection.optional
+        && !matches!(connection.properties.as_slice(), [property] if property.optional)
+}
+
+/// Attaches the candidate values of `VALUES` blocks to every copy of their
+/// entity in the connections `in_scope`. Returns `false` if a variable has no
+/// entity there.
+fn attach_values(
+    values: Vec<(String, Vec<String>)>,
+    connections: &mut [Connection],
+    in_scope: fn(&Connection) -> bool,
+) -> bool {
+    values.into_iter().all(|(id, values)| {
+        let mut attached = false;
+        for connection in connections.iter_mut().filter(|c| in_scope(c)) {
+            for entity in connection.entities_mut() {
+                if entity.id == id {
+                    entity.values = values.clone();
+                    attached = true;
+                }
+            }
+        }
+        attached
+    })
+}
+
+/// The variables of all entities with candidate values.
+fn valued_variables(graph: &QueryGraph) -> HashSet<String> {
+    graph
+        .all_connections()
+        .into_iter()
+        .flat_map(|c| c.entities())
+        .filter(|entity| !entity.values.is_empty())
+        .map(|entity| entity.id.clone())
+        .collect()
+}
+
+/// The inner pattern of an aggregating query, the aggregates of entities and
+/// the remaining computed variables.
+type Aggregation = (GraphPattern, Vec<(String, Aggregate)>, Vec<ComputedVariable>);
+
+/// Peels `Extend(...(Filter(Group(inner))))` off the pattern of an aggregating
+/// query, where the extensions bind the aliases of the aggregates and the
+/// filter is the `HAVING` condition. Returns the inner pattern, the
+/// aggregates together with the variable they aggregate and the extensions
+/// which are no aggregate of a single variable, e.g. `(COUNT(*) AS ?count)`.
+/// Returns `None` if an order key is an aggregate without an alias.
+///
+/// Without aggregation, the outermost extensions are the projection
+/// expressions, or `BIND`s at the end of the WHERE clause if their variable
+/// is not projected.
+fn unwrap_aggregation(
+    pattern: GraphPattern,
+    settings: &mut QuerySettings,
+    projection_vars: Option<&HashSet<String>>,
+) -> Option<Aggregation> {
+    fn is_aggregation(pattern: &GraphPattern) -> bool {
+        match pattern {
+            GraphPattern::Extend { inner, .. } => is_aggregation(inner),
+            GraphPattern::Filter { inner, .. } => matches!(**inner, GraphPattern::Group { .. }),
+            GraphPattern::Group { .. } => true,
+            _ => false,
+        }
+    }
+
+    let aggregation = is_aggregation(&pattern);
+    let mut pattern = pattern;
+    let mut aliases = Vec::new();
+    while let GraphPattern::Extend {
+        inner,
+        variable,
+        expression,
+    } = pattern
+    {
+        aliases.push((format!("?{}", variable.as_str()), expression));
+        pattern = *inner;
+    }
+    // aliases are collected outermost first
+    aliases.reverse();
+
+    if !aggregation {
+        let computed = aliases
+            .into_iter()
+            .map(|(alias, expression)| ComputedVariable {
+                bind: !projection_vars.is_some_and(|vars| vars.contains(&alias)),
+                alias,
+                expression: algebra_to_expression(&expression),
+                selected_for_projection: true,
+            })
+            .collect();
+        return Some((pattern, vec![], computed));
+    }
+    let (pattern, having) = match pattern {
+        GraphPattern::Filter { expr, inner } => (*inner, Some(expr)),
+        other => (other, None),
+    };
+    let (inner, aggregates) = match pattern {
+        GraphPattern::Group {
+            inner,
+            variables,
+            aggregates,
+        } => {
+            settings.group_by = variables.iter().map(|v| format!("?{}", v.as_str())).collect();
+            (*inner, aggregates)
+        }
+        other => (other, vec![]),
+    };
+
+    // spargebra binds every aggregate to an internal variable
+    let internal: HashMap<String, Expression> = aggregates
+        .iter()
+        .filter_map(|(variable, aggregate)| {
+            algebra_to_aggregate(aggregate).map(|e| (format!("?{}", variable.as_str()), e))
+        })
+        .collect();
+
+    if let Some(having) = having {
+        settings.having = split_conjunction(&having)
+            .into_iter()
+            .map(|condition| substitute_variables(&algebra_to_expression(condition), &internal))
+            .collect();
+    }
+
+    // ORDER BY DESC(COUNT(?y)) orders by the internal variable, which is
+    // the alias of the same aggregate
+    for key in &mut settings.order_by {
+        if let Some(aggregate) = internal.get(&key.variable) {
+            key.variable = aliases
+                .iter()
+                .find(|(_, expression)| {
+                    substitute_variables(&algebra_to_expression(expression), &internal) == *aggregate
+                })
+                .map(|(alias, _)| alias.clone())?;
+        }
+    }
+
+    let mut entity_aggregates = Vec::new();
+    let mut computed = Vec::new();
+    for (alias, expression) in aliases {
+        let expression = substitute_variables(&algebra_to_expression(&expression), &internal);
+        if let Expression::Aggregate {
+            function,
+            distinct,
+            argument: Some(argument),
+            separator,
+        } = &expression
+        {
+            if let Expression::Variable { name } = argument.as_ref() {
+                entity_aggregates.push((
+                    name.clone(),
+                    Aggregate {
+                        function: *function,
+                        distinct: *distinct,
+                        alias,
+                        separator: separator.clone(),
+                    },
+                ));
+                continue;
+            }
+        }
+        computed.push(ComputedVariable {
+            alias,
+            expression,
+            bind: false,
+            selected_for_projection: true,
+        });
+    }
+
+    Some((inner, entity_aggregates, computed))
+}
+
+/// The parts of a group which `ImportContext` collects besides its
+/// connections.
+#[derive(Default)]
+struct GroupParts {
+    filters: Vec<Expression>,
+    unions: Vec<UnionGroup>,
+    values: Vec<(String, Vec<String>)>,
+    computed: Vec<ComputedVariable>,
+    subqueries: Vec<SubQuery>,
+}
+
+/// State shared while walking the algebra tree of a parsed query.
+#[derive(Default)]
+struct ImportContext {
+    group_count: usize,
+    /// Filters that could not be attached to a single entity.
+    filters: Vec<Expression>,
+    unions: Vec<UnionGroup>,
+    /// Candidate values of single variable `VALUES` blocks by variable.
+    values: Vec<(String, Vec<String>)>,
+    /// `BIND`s inside the WHERE clause.
+    computed: Vec<ComputedVariable>,
+    subqueries: Vec<SubQuery>,
+    /// The settings of a label service.
+    labels: Option<LabelSettings>,
+    /// The projected variables of the query, if they are listed.
+    projection: Option<HashSet<String>>,
+    /// Set if a part of the pattern cannot be represented without changing
+    /// the query, e.g. a `BIND` whose variable is used before it is rendered.
+    unsupported: bool,
+}
+
+impl ImportContext {
+    /// Returns a fresh group name, e.g. `optional1`.
+    fn next_group(&mut self, kind: &str) -> String {
+        self.group_count += 1;
+        format!("{}{}", kind, self.group_count)
+    }
+
+    /// Returns a fresh group name if the connections of one block have to be
+    /// grouped, i.e. if there is more than one.
+    fn block_group(&mut self, connections: &[Connection], kind: &str) -> Option<String> {
+        if connections.len() > 1 {
+            Some(self.next_group(kind))
+        } else {
+            None
+        }
+    }
+
+    /// Walks the pattern of a `MINUS` or `FILTER NOT EXISTS` block, whose
+    /// filters stay inside the block.
+    fn negated(&mut self, pattern: GraphPattern, negation: Negation) -> Vec<Connection> {
+        let mut connections = self.block(pattern);
+        let group = self.block_group(&connections, "negation");
+        for connection in &mut connections {
+            connection.negation = Some(negation);
+            connection.group = group.clone();
+        }
+        connections
+    }
+
+    /// Walks the pattern of an `OPTIONAL` or negated block. Filters inside the
+    /// block which are not attached to a single entity and `BIND`s stay in the
+    /// block with its first connection, `VALUES` with the entities of the
+    /// block. Nested `OPTIONAL` blocks, negations, unions and subqueries
+    /// inside the block cannot be kept in it.
+    fn block(&mut self, pattern: GraphPattern) -> Vec<Connection> {
+        let outer = self.take_group();
+        let mut connections = match_bgp_or_path_to_vqg(pattern, self);
+        let inner = self.restore_group(outer);
+        if connections.iter().any(|c| c.optional || c.negation.is_some())
+            || !inner.unions.is_empty()
+            || !inner.subqueries.is_empty()
+            || !attach_values(inner.values, &mut connections, |_| true)
+        {
+            self.unsupported = true;
+        }
+        self.keep_in_block(inner.filters, &mut connections);
+        match connections.first_mut() {
+            Some(first) => first.computed.extend(inner.computed),
+            // a block without a connection cannot keep its BINDs
+            None => self.unsupported |= !inner.computed.is_empty(),
+        }
+        connections
+    }
+
+    /// Takes what has been collected for the current group, so that a nested
+    /// group starts empty.
+    fn take_group(&mut self) -> GroupParts {
+        GroupParts {
+            filters: std::mem::take(&mut self.filters),
+            unions: std::mem::take(&mut self.unions),
+            values: std::mem::take(&mut self.values),
+            computed: std::mem::take(&mut self.computed),
+            subqueries: std::mem::take(&mut self.subqueries),
+        }
+    }
+
+    /// Restores the `outer` group taken by `take_group` and returns what has
+    /// been collected for the nested group.
+    fn restore_group(&mut self, outer: GroupParts) -> GroupParts {
+        GroupParts {
+            filters: std::mem::replace(&mut self.filters, outer.filters),
+            unions: std::mem::replace(&mut self.unions, outer.unions),
+            values: std::mem::replace(&mut self.values, outer.values),
+            computed: std::mem::replace(&mut self.computed, outer.computed),
+            subqueries: std::mem::replace(&mut self.subqueries, outer.subqueries),
+        }
+    }
+
+    /// Keeps filters in the block of `connections`, or in the enclosing group
+    /// if the block has no connection to carry them.
+    fn keep_in_block(&mut self, filters: Vec<Expression>, connections: &mut [Connection]) {
+        match connections.first_mut() {
+            Some(first) => first.filters.extend(filters),
+            None => self.filters.extend(filters),
+        }
+    }
+
+    /// Walks one branch of a `UNION` with its own scope for filters, unions,
+    /// `VALUES` and `BIND`s. Subqueries inside the branch cannot be kept in it.
+    fn union_branch(&mut self, pattern: GraphPattern) -> UnionBranch {
+        let outer = self.take_group();
+        let mut connections = match_bgp_or_path_to_vqg(pattern, self);
+        let inner = self.restore_group(outer);
+        self.unsupported |= !inner.subqueries.is_empty()
+            || !attach_values(inner.values, &mut connections, in_group_scope);
+        UnionBranch {
+            connections,
+            filters: inner.filters,
+            unions: inner.unions,
+            computed: inner.computed,
+        }
+    }
+
+    /// Checks the `BIND`s of the current group from `start` on, which were
+    /// written before a pattern binding the `later` variables. Apart from a
+    /// `BIND` of a constant, which is rendered first, they are rendered after
+    /// the triples of their group, so the later pattern must neither use the
+    /// bound variable nor bind a variable of the expression which the
+    /// `earlier` pattern left unbound.
+    fn check_binds(&mut self, start: usize, earlier: &HashSet<String>, later: &HashSet<String>) {
+        let misplaced = self.computed[start..].iter().any(|computed| {
+            let variables = expression_variables(&computed.expression);
+            !variables.is_empty()
+                && (later.contains(&computed.alias)
+                    || variables.iter().any(|v| later.contains(v) && !earlier.contains(v)))
+        });
+        self.unsupported |= misplaced;
+    }
+
+    /// Attaches each conjunct of a filter expression to the first entity of the
+    /// mandatory `connections` it exclusively refers to, since an entity of an
+    /// optional or negated connection is filtered inside that block.
+    /// `NOT EXISTS { ... }` conjuncts become negated connections. Returns the
+    /// conjuncts which are not attached.
+    fn attach_filters(&mut self, expression: &AlgebraExpression, connections: &mut Vec<Connection>) -> Vec<Expression> {
+        let mut unattached = Vec::new();
+        for conjunct in split_conjunction(expression) {
+            if let AlgebraExpression::Not(inner) = conjunct {
+                if let AlgebraExpression::Exists(pattern) = inner.as_ref() {
+                    let negated = self.negated(pattern.as_ref().clone(), Negation::NotExists);
+                    connections.extend(negated);
+                    continue;
+                }
+            }
+            let filter = algebra_to_expression(conjunct);
+            let variables = expression_variables(&filter);
+            let entity = match variables.as_slice() {
+                [variable] => connections
+                    .iter_mut()
+                    .filter(|c| !c.optional && c.negation.is_none())
+                    .flat_map(|c| vec![&mut c.source, &mut c.target])
+                    .find(|entity| &entity.id == variable),
+                _ => None,
+            };
+            match entity {
+                Some(entity) => entity.filters.push(filter),
+                None => unattached.push(filter),
+            }
+        }
+        unattached
+    }
+}
+
+/// Reads the languages of a label service from its
+/// `bd:serviceParam wikibase:language "de,en"` triple.
+fn label_settings(inner: &GraphPattern) -> LabelSettings {
+    let mut settings = LabelSettings::default();
+    if let GraphPattern::Bgp { patterns } = inner {
+        for pattern in patterns {
+            if let (TermPattern::Literal(languages), NamedNodePattern::NamedNode(predicate)) =
+                (&pattern.object, &pattern.predicate)
+            {
+                if predicate.as_str() == LANGUAGE_PARAMETER {
+                    settings.languages = languages.value().split(',').map(|l| l.trim().to_string()).collect();
+                }
+            }
+        }
+    }
+    if settings.language_list() == DEFAULT_LANGUAGES {
+        settings.languages.clear();
+    }
+    settings
+}
+
+/// The variables a pattern binds, e.g. `?x` and `?y` of `?x wdt:P31 ?y`.
+fn in_scope_variables(pattern: &GraphPattern) -> HashSet<String> {
+    let mut variables = HashSet::new();
+    pattern.on_in_scope_variable(|variable| {
+        variables.insert(format!("?{}", variable.as_str()));
+    });
+    variables
+}
+
+/// Recognises the right hand side of a label block of the `rdfs:label`
+/// strategy, `OPTIONAL { ?x rdfs:label ?xLabel . FILTER(LANG(?xLabel) = "de") }`
+/// or its description and alias counterparts, and returns its language, if
+/// any. Only a block whose variable is projected as companion of a projected
+/// entity is a label block, otherwise its variable is used elsewhere.
+fn rdfs_label_language(
+    right: &GraphPattern,
+    expression: Option<&AlgebraExpression>,
+    projection: Option<&HashSet<String>>,
+) -> Option<Option<String>> {
+    let label = match right {
+        GraphPattern::Bgp { patterns } => match patterns.as_slice() {
+            [TriplePattern {
+                subject: TermPattern::Variable(subject),
+                predicate: NamedNodePattern::NamedNode(predicate),
+                object: TermPattern::Variable(label),
+            }] if COMPANIONS.iter().any(|(suffix, _, iri, local)| {
+                predicate.as_str() == format!("{}{}", iri, local)
+                    && label.as_str() == format!("{}{}", subject.as_str(), suffix)
+            }) =>
+            {
+                let projected = |variable: &Variable| {
+                    projection.is_some_and(|vars| vars.contains(&format!("?{}", variable.as_str())))
+                };
+                if !projected(subject) || !projected(label) {
+                    return None;
+                }
+                label
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match expression {
+        None => Some(None),
+        Some(AlgebraExpression::Equal(left, right)) => match (left.as_ref(), right.as_ref()) {
+            (AlgebraExpression::FunctionCall(Function::Lang, arguments), AlgebraExpression::Literal(language))
+                if matches!(arguments.as_slice(), [AlgebraExpression::Variable(v)] if v == label) =>
+            {
+                Some(Some(language.value().to_string()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// This will only match a subclass of [SPARQL queries](https://www.w3.org/TR/sparql11-query/).
 ///
 /// ```sparql
@@ -489,22 +2488,146 @@ fn query_to_vqg(query: &str) -> Vec<Connection> {
 //     SERVICE wikibase:label { bd:serviceParam wikibase:language "[AUTO_LANGUAGE],en". }
 //   }
 /// ```
-fn match_bgp_or_path_to_vqg(p: GraphPattern) -> Vec<Connection> {
+fn match_bgp_or_path_to_vqg(p: GraphPattern, context: &mut ImportContext) -> Vec<Connection> {
     match p {
         GraphPattern::Bgp { patterns: bgp } => bgp_to_vqg(bgp),
-        // ignore any service statements
-        GraphPattern::Service {
-            name: _n,
-            inner: _i,
-            silent: _s,
-        } => vec![],
+        // the label service only contributes its languages, any other service
+        // is ignored
+        GraphPattern::Service { name, inner, .. } => {
+            if matches!(&name, NamedNodePattern::NamedNode(n) if n.as_str() == LABEL_SERVICE) {
+                context.labels = Some(label_settings(&inner));
+            }
+            vec![]
+        }
         // this will match e.g. a BGP and a SERVICE statement
         GraphPattern::Join { left: l, right: r } => {
-            let l_parsed = match_bgp_or_path_to_vqg(*l);
-            let r_parsed = match_bgp_or_path_to_vqg(*r);
+            let (start, earlier, later) = (context.computed.len(), in_scope_variables(&l), in_scope_variables(&r));
+            let l_parsed = match_bgp_or_path_to_vqg(*l, context);
+            context.check_binds(start, &earlier, &later);
+            let r_parsed = match_bgp_or_path_to_vqg(*r, context);
+            l_parsed.into_iter().chain(r_parsed).collect()
+        }
+        // OPTIONAL { ... }: the right hand side becomes optional connections,
+        // several triples in one block share a common group
+        GraphPattern::LeftJoin {
+            left: l,
+            right: r,
+            expression: e,
+        } => {
+            let (start, earlier) = (context.computed.len(), in_scope_variables(&l));
+            let mut later = in_scope_variables(&r);
+            later.extend(e.iter().flat_map(|e| expression_variables(&algebra_to_expression(e))));
+            let l_parsed = match_bgp_or_path_to_vqg(*l, context);
+            context.check_binds(start, &earlier, &later);
+            // a label block of the rdfs:label strategy
+            if let Some(language) = rdfs_label_language(&r, e.as_ref(), context.projection.as_ref()) {
+                let labels = context.labels.get_or_insert_with(|| LabelSettings {
+                    strategy: LabelStrategy::RdfsLabel,
+                    ..LabelSettings::default()
+                });
+                if let Some(language) = language.filter(|language| !labels.languages.contains(language)) {
+                    labels.languages.push(language);
+                }
+                return l_parsed;
+            }
+            let mut r_parsed = context.block(*r);
+            // a FILTER inside the OPTIONAL block
+            if let Some(e) = e {
+                let unattached = context.attach_filters(&e, &mut r_parsed);
+                context.keep_in_block(unattached, &mut r_parsed);
+                // a FILTER NOT EXISTS inside the OPTIONAL block cannot be kept in it
+                context.unsupported |= r_parsed.iter().any(|c| c.negation.is_some());
+            }
+            let group = context.block_group(&r_parsed, "optional");
+            for connection in &mut r_parsed {
+                connection.optional = true;
+                connection.group = group.clone();
+            }
+            l_parsed.into_iter().chain(r_parsed).collect()
+        }
+        GraphPattern::Minus { left: l, right: r } => {
+            let (start, earlier, later) = (context.computed.len(), in_scope_variables(&l), in_scope_variables(&r));
+            let l_parsed = match_bgp_or_path_to_vqg(*l, context);
+            context.check_binds(start, &earlier, &later);
+            let r_parsed = context.negated(*r, Negation::Minus);
             l_parsed.into_iter().chain(r_parsed).collect()
         }
-        GraphPattern::Distinct { inner } => match_bgp_or_path_to_vqg(*inner),
+        // { ... } UNION { ... } UNION { ... } is nested to the left
+        GraphPattern::Union { left: l, right: r } => {
+            fn flatten(pattern: GraphPattern, branches: &mut Vec<GraphPattern>) {
+                match pattern {
+                    GraphPattern::Union { left: l, right: r } => {
+                        flatten(*l, branches);
+                        flatten(*r, branches);
+                    }
+                    other => branches.push(other),
+                }
+            }
+
+            let mut patterns = Vec::new();
+            flatten(*l, &mut patterns);
+            flatten(*r, &mut patterns);
+            let branches = patterns
+                .into_iter()
+                .map(|pattern| context.union_branch(pattern))
+                .collect();
+            context.unions.push(UnionGroup { branches });
+            vec![]
+        }
+        // only single variable VALUES blocks can be represented by an entity
+        GraphPattern::Values {
+            variables,
+            bindings,
+        } => {
+            if let [variable] = variables.as_slice() {
+                let values = bindings
+                    .iter()
+                    .map(|row| match row.first().cloned().flatten() {
+                        Some(term) => ground_term_to_string(&term),
+                        None => String::from("UNDEF"),
+                    })
+                    .collect();
+                context.values.push((format!("?{}", variable.as_str()), values));
+            } else {
+                context.unsupported = true;
+            }
+            vec![]
+        }
+        GraphPattern::Filter { expr, inner } => {
+            let mut connections = match_bgp_or_path_to_vqg(*inner, context);
+            let unattached = context.attach_filters(&expr, &mut connections);
+            context.filters.extend(unattached);
+            connections
+        }
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => {
+            let connections = match_bgp_or_path_to_vqg(*inner, context);
+            context.computed.push(ComputedVariable {
+                alias: format!("?{}", variable.as_str()),
+                expression: algebra_to_expression(&expression),
+                bind: true,
+                selected_for_projection: true,
+            });
+            connections
+        }
+        // a nested { SELECT ... }, always projected and possibly wrapped in
+        // solution modifiers
+        pattern @ GraphPattern::Project { .. }
+        | pattern @ GraphPattern::Slice { .. }
+        | pattern @ GraphPattern::Distinct { .. }
+        | pattern @ GraphPattern::Reduced { .. } => {
+            match try_select_to_graph(pattern) {
+                Some(graph) => context.subqueries.push(SubQuery {
+                    label: String::new(),
+                    graph,
+                }),
+                None => context.unsupported = true,
+            }
+            vec![]
+        }
         GraphPattern::Path {
             subject: s,
             path: p,
@@ -537,6 +2660,11 @@ fn connection_constructor(
             },
             selected_for_projection: true, // Default to true
             distinct: false,
+            filters: vec![],
+            aggregates: vec![],
+            values: vec![],
+            snak_type: None,
+            companions: Companions::default(),
         },
         target: Entity {
             id: object_name.clone(),
@@ -547,6 +2675,11 @@ fn connection_constructor(
             },
             selected_for_projection: true, // Default to true
             distinct: false,
+            filters: vec![],
+            aggregates: vec![],
+            values: vec![],
+            snak_type: None,
+            companions: Companions::default(),
         },
         properties: vec![Property {
             id: predicate_name.clone(),
@@ -559,6 +2692,18 @@ fn connection_constructor(
             properties: vec![],
             path_type: None,
             modifier: None,
+            optional: false,
+            qualifiers: vec![],
+            statement: None,
+            statement_selected_for_projection: false,
+            references: vec![],
+            rank: None,
+            value_node: None,
         }],
+        optional: false,
+        group: None,
+        negation: None,
+        filters: vec![],
+        computed: vec![],
     }
 }
@@ -37,7 +37,8 @@ const fqdnRegex = new RegExp(/(?:[\w-]+\.)+[\w-]+/);
 
 function convertConnectionsToPrefixedRepresentation(connections: Array<ConnectionInterfaceType>, vqgEntities: Array<EntityType>): Promise<ConnectionInterfaceType>[] {
     // this function takes in a connections array from e.g. the rust backend
-    // and replaces the full URL with prefixes
+    // and replaces the full URL with prefixes. The backend already splits IRIs of known namespaces
+    // into a local id and a prefix, so the full IRI is rebuilt from both to find the data source.
 
     // if no prefix can be found, this means that the item is not from a data source the visual query builder knows
     // In this case, the prefix will not be replaced.
@@ -58,8 +59,10 @@ function convertConnectionsToPrefixedRepresentation(connections: Array<Connectio
             }
 
 
+            const fullIri = entity.prefix?.iri ? entity.prefix.iri + entity.id : entity.id;
+
             // find out, which data source the entity might belong to
-            const fqdn = fqdnRegex.exec(entity.id);
+            const fqdn = fqdnRegex.exec(fullIri);
             if (!fqdn || !fqdn[0]) {
                 return entity;
             }
@@ -137,13 +140,13 @@ function convertConnectionsToPrefixedRepresentation(connections: Array<Connectio
                 // keys "itemPrefix" and "propertyPrefix"
                 const matchingDatasourceForEntity = dataSources.value.find(s => s[prefixKey].iri.includes(fqdn));
                 if (matchingDatasourceForEntity) {
-                    const isTheRightPrefix = entity.id.includes(matchingDatasourceForEntity[prefixKey].iri);
+                    const isTheRightPrefix = fullIri.includes(matchingDatasourceForEntity[prefixKey].iri);
                     // this part is very important! If it would not return false, the differentiation between prop and
                     //  item would not be correct.
                     if (!isTheRightPrefix) {
                         return false;
                     }
-                    const newIdentifier = _replace_helper(entity.id, matchingDatasourceForEntity[prefixKey].iri)
+                    const newIdentifier = _replace_helper(fullIri, matchingDatasourceForEntity[prefixKey].iri)
                     const matchInVQG = _vqg_metadata_helper(newIdentifier, matchingDatasourceForEntity[prefixKey].iri, matchingDatasourceForEntity[prefixKey].abbreviation);
                     if (matchInVQG === false) {
                         // try to fetch from wikidata
@@ -0,0 +1,224 @@
+//! Compaction of the full IRIs of an imported graph into an id and a prefix,
+//! e.g. `<http://www.wikidata.org/entity/Q5879>` into `Q5879` and `wd:`, as
+//! the editor builds them.
+
+use crate::{is_plain_value, wikibase, Connection, Entity, Prefix, Property, QueryGraph, COMPANIONS};
+
+/// Vocabularies besides the Wikibase namespaces which are compacted even if
+/// the query does not declare them.
+const VOCABULARIES: [(&str, &str); 4] = [
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ("owl", "http://www.w3.org/2002/07/owl#"),
+    ("prov", "http://www.w3.org/ns/prov#"),
+    ("wikibase", "http://wikiba.se/ontology#"),
+];
+
+/// The `PREFIX` declarations of a query, in the order they are declared.
+/// Only the prologue is read, i.e. `PREFIX` and `BASE` declarations and
+/// comments up to the first other token, so that `prefix` in a comment or a
+/// literal of the query is never taken for a declaration.
+pub fn declared_prefixes(query: &str) -> Vec<Prefix> {
+    let mut prefixes = Vec::new();
+    let mut rest = skip_comments(query);
+    loop {
+        if let Some(declaration) = strip_keyword(rest, "prefix") {
+            match parse_declaration(declaration) {
+                Some((prefix, after)) => {
+                    prefixes.push(prefix);
+                    rest = skip_comments(after);
+                }
+                None => break,
+            }
+        } else if let Some(declaration) = strip_keyword(rest, "base") {
+            match parse_iri(declaration.trim_start()) {
+                Some((_, after)) => rest = skip_comments(after),
+                None => break,
+            }
+        } else {
+            break;
+        }
+    }
+    prefixes
+}
+
+/// Skips whitespace and `#` comments.
+fn skip_comments(mut input: &str) -> &str {
+    loop {
+        input = input.trim_start();
+        match input.strip_prefix('#') {
+            Some(comment) => input = comment.find('\n').map_or("", |end| &comment[end..]),
+            None => return input,
+        }
+    }
+}
+
+/// Strips a keyword, in any case, off the start of the input.
+fn strip_keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = input.get(keyword.len()..)?;
+    (input[..keyword.len()].eq_ignore_ascii_case(keyword) && !rest.starts_with(is_name_char)).then_some(rest)
+}
+
+/// Parses ` ex: <http://example.org/>` following a `PREFIX` keyword and
+/// returns the rest of the input.
+fn parse_declaration(declaration: &str) -> Option<(Prefix, &str)> {
+    let declaration = declaration.trim_start();
+    let colon = declaration.find(':')?;
+    let abbreviation = &declaration[..colon];
+    if !abbreviation.chars().all(is_name_char) {
+        return None;
+    }
+    let (iri, rest) = parse_iri(declaration[colon + 1..].trim_start())?;
+    if iri.is_empty() {
+        return None;
+    }
+    let prefix = Prefix {
+        iri: iri.to_string(),
+        abbreviation: abbreviation.to_string(),
+    };
+    Some((prefix, rest))
+}
+
+/// Parses `<http://example.org/>` and returns the IRI and the rest of the
+/// input.
+fn parse_iri(input: &str) -> Option<(&str, &str)> {
+    let iri = input.strip_prefix('<')?;
+    let end = iri.find('>')?;
+    Some((&iri[..end], &iri[end + 1..]))
+}
+
+/// Compacts the full IRIs of the entities and properties of a graph, its
+/// template and its subqueries. The `declared` prefixes of the query take
+/// precedence over the known ones; a known prefix is skipped if the query
+/// declares its abbreviation for another IRI.
+pub fn compact_graph(graph: &mut QueryGraph, declared: &[Prefix]) {
+    let mut prefixes: Vec<Prefix> = declared.to_vec();
+    let known = wikibase::known_prefixes().into_iter().chain(
+        COMPANIONS
+            .iter()
+            .map(|(_, abbreviation, iri, _)| (*abbreviation, *iri))
+            .chain(VOCABULARIES)
+            .map(|(abbreviation, iri)| Prefix {
+                iri: iri.to_string(),
+                abbreviation: abbreviation.to_string(),
+            }),
+    );
+    for prefix in known {
+        if !prefixes.iter().any(|p| p.abbreviation == prefix.abbreviation || p.iri == prefix.iri) {
+            prefixes.push(prefix);
+        }
+    }
+    compact(graph, &prefixes);
+}
+
+fn compact(graph: &mut QueryGraph, prefixes: &[Prefix]) {
+    for connection in graph.all_connections_mut() {
+        compact_connection(connection, prefixes);
+    }
+    for connection in &mut graph.template {
+        compact_connection(connection, prefixes);
+    }
+    for subquery in &mut graph.subqueries {
+        compact(&mut subquery.graph, prefixes);
+    }
+}
+
+fn compact_connection(connection: &mut Connection, prefixes: &[Prefix]) {
+    for entity in connection.entities_mut() {
+        compact_entity(entity, prefixes);
+    }
+    for property in &mut connection.properties {
+        compact_property(property, prefixes);
+    }
+}
+
+fn compact_entity(entity: &mut Entity, prefixes: &[Prefix]) {
+    if let Some((id, prefix)) = compact_iri(&entity.id, &entity.prefix, prefixes) {
+        if entity.label == entity.id {
+            entity.label = format!("{}:{}", prefix.abbreviation, id);
+        }
+        entity.id = id;
+        entity.prefix = prefix;
+    }
+    compact_values(entity, prefixes);
+}
+
+/// Compacts the candidate values of a variable entity if all of its IRIs
+/// share one namespace, which becomes the prefix of the entity. Values of
+/// several namespaces stay full IRIs, since their prefixes could not be
+/// declared; literals and `UNDEF` are kept as they are.
+fn compact_values(entity: &mut Entity, prefixes: &[Prefix]) {
+    if !entity.prefix.iri.is_empty() {
+        return;
+    }
+    let mut namespace: Option<Prefix> = None;
+    let mut compacted = Vec::with_capacity(entity.values.len());
+    for value in &entity.values {
+        if is_plain_value(value) {
+            compacted.push(value.clone());
+            continue;
+        }
+        match compact_iri(value, &entity.prefix, prefixes) {
+            // a local name like `42` would be taken for a literal
+            Some((id, prefix)) if !is_plain_value(&id) && namespace.as_ref().is_none_or(|n| *n == prefix) => {
+                namespace = Some(prefix);
+                compacted.push(id);
+            }
+            _ => return,
+        }
+    }
+    if let Some(prefix) = namespace {
+        entity.prefix = prefix;
+        entity.values = compacted;
+    }
+}
+
+fn compact_property(property: &mut Property, prefixes: &[Prefix]) {
+    if let Some((id, prefix)) = compact_iri(&property.id, &property.prefix, prefixes) {
+        if property.label == property.id {
+            property.label = format!("{}:{}", prefix.abbreviation, id);
+        }
+        property.id = id;
+        property.prefix = prefix;
+    }
+    for inner in &mut property.properties {
+        compact_property(inner, prefixes);
+    }
+    for qualifier in &mut property.qualifiers {
+        compact_property(&mut qualifier.property, prefixes);
+    }
+    for snak in property.references.iter_mut().flat_map(|r| &mut r.snaks) {
+        compact_property(&mut snak.property, prefixes);
+    }
+}
+
+/// The local name and the prefix of a full IRI `<...>` without a prefix. Of
+/// several matching prefixes the longest wins, so that e.g. `wdt:P31` is
+/// preferred over `wd:prop/direct/P31`, which would be no valid local name
+/// anyway.
+fn compact_iri(id: &str, current: &Prefix, prefixes: &[Prefix]) -> Option<(String, Prefix)> {
+    if !current.iri.is_empty() {
+        return None;
+    }
+    let iri = id.strip_prefix('<')?.strip_suffix('>')?;
+    let mut best: Option<&Prefix> = None;
+    for prefix in prefixes {
+        let matches = iri.starts_with(&prefix.iri) && is_local_name(&iri[prefix.iri.len()..]);
+        if matches && best.is_none_or(|best| prefix.iri.len() > best.iri.len()) {
+            best = Some(prefix);
+        }
+    }
+    best.map(|prefix| (iri[prefix.iri.len()..].to_string(), prefix.clone()))
+}
+
+/// Whether a local name can be written after a prefix as it is, e.g. `Q5`
+/// but not `entity/Q5` or `Q5.`.
+fn is_local_name(local: &str) -> bool {
+    !local.is_empty()
+        && local.chars().all(is_name_char)
+        && !local.starts_with(['-', '.'])
+        && !local.ends_with('.')
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
@@ -0,0 +1,1012 @@
+//! Wikibase specific rendering: the namespaces of a Wikibase instance and the
+//! statement node pattern of qualified, referenced and ranked edges and the
+//! special "unknown value" and "no value" targets.
+
+use crate::{
+    get_iri, Companions, ComparisonOperator, Connection, Entity, Expression, Prefix, Property, QueryGraph, Rank, Reference, Snak,
+    SnakType, UnionGroup, ValueComponent, ValueKind, ValueNode,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// The namespaces of a Wikibase instance, relative to its concept base IRI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Namespace {
+    /// `wd:`
+    Entity,
+    /// `wdt:`
+    Direct,
+    /// `p:`
+    Claim,
+    /// `ps:`
+    Statement,
+    /// `psv:`
+    StatementValue,
+    /// `pq:`
+    Qualifier,
+    /// `pqv:`
+    QualifierValue,
+    /// `pr:`
+    Reference,
+    /// `wdno:`
+    NoValue,
+    /// `wds:`
+    StatementNode,
+    /// `wdref:`
+    ReferenceNode,
+}
+
+/// The longest paths first, so that e.g. `prop/direct/` is preferred over `prop/`.
+const NAMESPACES: [(Namespace, &str, &str); 11] = [
+    (Namespace::StatementValue, "prop/statement/value/", "psv"),
+    (Namespace::QualifierValue, "prop/qualifier/value/", "pqv"),
+    (Namespace::Statement, "prop/statement/", "ps"),
+    (Namespace::Qualifier, "prop/qualifier/", "pq"),
+    (Namespace::Reference, "prop/reference/", "pr"),
+    (Namespace::NoValue, "prop/novalue/", "no"),
+    (Namespace::Direct, "prop/direct/", "t"),
+    (Namespace::Claim, "prop/", "p"),
+    (Namespace::StatementNode, "entity/statement/", "s"),
+    (Namespace::Entity, "entity/", ""),
+    (Namespace::ReferenceNode, "reference/", "ref"),
+];
+
+const WIKIDATA_BASE: &str = "http://www.wikidata.org/";
+const PROV_IRI: &str = "http://www.w3.org/ns/prov#";
+const WAS_DERIVED_FROM: &str = "wasDerivedFrom";
+const ONTOLOGY_IRI: &str = "http://wikiba.se/ontology#";
+/// The `wikibase:` predicates of the value node components.
+const VALUE_KINDS: [(ValueKind, &str); 12] = [
+    (ValueKind::TimeValue, "timeValue"),
+    (ValueKind::TimePrecision, "timePrecision"),
+    (ValueKind::TimeTimezone, "timeTimezone"),
+    (ValueKind::TimeCalendarModel, "timeCalendarModel"),
+    (ValueKind::QuantityAmount, "quantityAmount"),
+    (ValueKind::QuantityUnit, "quantityUnit"),
+    (ValueKind::QuantityUpperBound, "quantityUpperBound"),
+    (ValueKind::QuantityLowerBound, "quantityLowerBound"),
+    (ValueKind::GeoLatitude, "geoLatitude"),
+    (ValueKind::GeoLongitude, "geoLongitude"),
+    (ValueKind::GeoPrecision, "geoPrecision"),
+    (ValueKind::GeoGlobe, "geoGlobe"),
+];
+const RDF_TYPE: &str = "<http://www.w3.org/1999/02/22-rdf-syntax-ns#type>";
+
+/// The abbreviations of the namespaces of a Wikibase instance.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespacePrefixes {
+    pub entity: String,
+    pub direct: String,
+    pub claim: String,
+    pub statement: String,
+    pub statement_value: String,
+    pub qualifier: String,
+    pub qualifier_value: String,
+    pub reference: String,
+    pub no_value: String,
+    pub statement_node: String,
+    pub reference_node: String,
+}
+
+impl NamespacePrefixes {
+    fn get(&self, namespace: Namespace) -> &str {
+        match namespace {
+            Namespace::Entity => &self.entity,
+            Namespace::Direct => &self.direct,
+            Namespace::Claim => &self.claim,
+            Namespace::Statement => &self.statement,
+            Namespace::StatementValue => &self.statement_value,
+            Namespace::Qualifier => &self.qualifier,
+            Namespace::QualifierValue => &self.qualifier_value,
+            Namespace::Reference => &self.reference,
+            Namespace::NoValue => &self.no_value,
+            Namespace::StatementNode => &self.statement_node,
+            Namespace::ReferenceNode => &self.reference_node,
+        }
+    }
+
+    /// The abbreviations of a stem, e.g. `fgt:`, `fgp:` and `fgps:` for `fg`.
+    /// Wikidata abbreviates the property namespaces without its stem, e.g.
+    /// `p:` and `pq:`.
+    fn of_stem(stem: &str) -> NamespacePrefixes {
+        let abbreviation = |namespace: Namespace| {
+            let (_, _, suffix) = NAMESPACES.iter().find(|(n, _, _)| *n == namespace).unwrap();
+            match namespace {
+                Namespace::Entity
+                | Namespace::Direct
+                | Namespace::NoValue
+                | Namespace::StatementNode
+                | Namespace::ReferenceNode => format!("{}{}", stem, suffix),
+                _ if stem == "wd" => suffix.to_string(),
+                _ => format!("{}{}", stem, suffix),
+            }
+        };
+        NamespacePrefixes {
+            entity: abbreviation(Namespace::Entity),
+            direct: abbreviation(Namespace::Direct),
+            claim: abbreviation(Namespace::Claim),
+            statement: abbreviation(Namespace::Statement),
+            statement_value: abbreviation(Namespace::StatementValue),
+            qualifier: abbreviation(Namespace::Qualifier),
+            qualifier_value: abbreviation(Namespace::QualifierValue),
+            reference: abbreviation(Namespace::Reference),
+            no_value: abbreviation(Namespace::NoValue),
+            statement_node: abbreviation(Namespace::StatementNode),
+            reference_node: abbreviation(Namespace::ReferenceNode),
+        }
+    }
+}
+
+/// The namespaces of one Wikibase instance: its concept base IRI, e.g.
+/// `http://www.wikidata.org/`, and the abbreviation of each namespace, e.g.
+/// `wd:`, `wdt:` and `p:`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WikibaseFamily {
+    /// The name of the data source, e.g. `Wikidata`.
+    #[serde(default)]
+    pub name: String,
+    pub base: String,
+    pub prefixes: NamespacePrefixes,
+    /// The preferred label languages of the data source.
+    #[serde(default)]
+    pub languages: Vec<String>,
+}
+
+impl WikibaseFamily {
+    fn new(name: &str, base: &str, stem: &str, languages: &[&str]) -> WikibaseFamily {
+        WikibaseFamily {
+            name: name.to_string(),
+            base: base.to_string(),
+            prefixes: NamespacePrefixes::of_stem(stem),
+            languages: languages.iter().map(|language| language.to_string()).collect(),
+        }
+    }
+
+    /// The prefix of one namespace of this family.
+    pub fn prefix(&self, namespace: Namespace) -> Prefix {
+        let (_, path, _) = NAMESPACES.iter().find(|(n, _, _)| *n == namespace).unwrap();
+        Prefix {
+            iri: format!("{}{}", self.base, path),
+            abbreviation: self.prefixes.get(namespace).to_string(),
+        }
+    }
+
+    /// Splits a full IRI into the family, the namespace and the local name, e.g.
+    /// `http://www.wikidata.org/prop/direct/P69` into Wikidata, `Direct` and
+    /// `P69`. The family of an unregistered base IRI is derived from the
+    /// abbreviation the IRI was written with, if any.
+    pub fn resolve(iri: &str, abbreviation: Option<&str>) -> Option<(WikibaseFamily, Namespace, String)> {
+        let split = iri.rfind('/')? + 1;
+        let (namespace_iri, local) = iri.split_at(split);
+        if local.is_empty() {
+            return None;
+        }
+        let (namespace, path, suffix) = NAMESPACES
+            .iter()
+            .find(|(_, path, _)| namespace_iri.ends_with(path) && namespace_iri.len() > path.len())?;
+        let base = &namespace_iri[..namespace_iri.len() - path.len()];
+        if !base.ends_with('/') {
+            return None;
+        }
+        if let Some(family) = REGISTRY.with(|registry| registry.borrow().by_base(base).cloned()) {
+            return Some((family, *namespace, local.to_string()));
+        }
+
+        let stem = match abbreviation {
+            // Wikidata abbreviates e.g. `p:` without its stem
+            Some(abbreviation) if !abbreviation.is_empty() && abbreviation == *suffix => "wd",
+            Some(abbreviation) if !abbreviation.is_empty() => {
+                abbreviation.strip_suffix(suffix).unwrap_or(abbreviation)
+            }
+            // without an abbreviation there is nothing to derive a stem from
+            _ => "wb",
+        };
+        Some((WikibaseFamily::new("", base, stem, &[]), *namespace, local.to_string()))
+    }
+
+    /// The family and the local name of a property, if it belongs to a
+    /// Wikibase instance.
+    pub fn of_property(property: &Property) -> Option<(WikibaseFamily, String)> {
+        WikibaseFamily::resolve_property(property).map(|(family, _, local)| (family, local))
+    }
+
+    /// Like `resolve`, for the IRI of a property.
+    fn resolve_property(property: &Property) -> Option<(WikibaseFamily, Namespace, String)> {
+        let (iri, abbreviation) = if property.id.starts_with('<') {
+            (property.id.trim_start_matches('<').trim_end_matches('>').to_string(), None)
+        } else if !property.prefix.iri.is_empty() && !property.id.contains(':') && !property.id.starts_with('?') {
+            (
+                format!("{}{}", property.prefix.iri, property.id),
+                Some(property.prefix.abbreviation.as_str()),
+            )
+        } else {
+            return None;
+        };
+        WikibaseFamily::resolve(&iri, abbreviation)
+    }
+}
+
+/// The known Wikibase instances, Wikidata, FactGrid and MiMoText and custom
+/// ones, e.g. from the settings of a graph.
+#[derive(Clone, Debug)]
+pub struct WikibaseRegistry {
+    families: Vec<WikibaseFamily>,
+}
+
+impl Default for WikibaseRegistry {
+    fn default() -> Self {
+        // MiMoText abbreviates its item namespaces with `mmd`, but its
+        // property namespaces with `mm`, e.g. `mmdt:` and `mmp:`
+        let mut mimotext = WikibaseFamily::new("MiMoText", "http://data.mimotext.uni-trier.de/", "mm", &["en"]);
+        let item_prefixes = NamespacePrefixes::of_stem("mmd");
+        mimotext.prefixes = NamespacePrefixes {
+            entity: item_prefixes.entity,
+            direct: item_prefixes.direct,
+            no_value: item_prefixes.no_value,
+            statement_node: item_prefixes.statement_node,
+            reference_node: item_prefixes.reference_node,
+            ..mimotext.prefixes
+        };
+        WikibaseRegistry {
+            families: vec![
+                WikibaseFamily::new("Wikidata", WIKIDATA_BASE, "wd", &[]),
+                WikibaseFamily::new("FactGrid", "https://database.factgrid.de/", "fg", &["de", "en"]),
+                mimotext,
+            ],
+        }
+    }
+}
+
+impl WikibaseRegistry {
+    /// The built-in families and the `custom` ones, which take precedence.
+    pub fn with_custom(custom: &[WikibaseFamily]) -> WikibaseRegistry {
+        let mut registry = WikibaseRegistry::default();
+        registry.families.splice(0..0, custom.iter().cloned());
+        registry
+    }
+
+    fn by_base(&self, base: &str) -> Option<&WikibaseFamily> {
+        self.families.iter().find(|family| family.base == base)
+    }
+
+    fn by_name(&self, name: &str) -> Option<&WikibaseFamily> {
+        self.families.iter().find(|family| family.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The prefixes of every namespace of every family.
+    fn prefixes(&self) -> Vec<Prefix> {
+        self.families
+            .iter()
+            .flat_map(|family| NAMESPACES.iter().map(move |(namespace, _, _)| family.prefix(*namespace)))
+            .collect()
+    }
+}
+
+thread_local! {
+    /// The registry of the current conversion, see `with_registry`.
+    static REGISTRY: RefCell<WikibaseRegistry> = RefCell::new(WikibaseRegistry::default());
+}
+
+/// Runs a conversion with a registry. The registry is not threaded through
+/// every rendering function, but installed for the duration of `f`.
+pub fn with_registry<T>(registry: WikibaseRegistry, f: impl FnOnce() -> T) -> T {
+    let previous = REGISTRY.with(|current| current.replace(registry));
+    let result = f();
+    REGISTRY.with(|current| current.replace(previous));
+    result
+}
+
+/// The preferred label languages of a registered data source.
+pub fn languages_of(data_source: &str) -> Vec<String> {
+    REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .by_name(data_source)
+            .map(|family| family.languages.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// The prefixes of the namespaces of all registered Wikibase instances.
+pub fn known_prefixes() -> Vec<Prefix> {
+    REGISTRY.with(|registry| registry.borrow().prefixes())
+}
+
+/// Whether a property is rendered through a statement node, i.e. it has a
+/// statement variable, qualifiers, references, a rank or a value node and is
+/// no property path.
+pub fn is_statement(property: &Property) -> bool {
+    (property.statement.is_some()
+        || !property.qualifiers.is_empty()
+        || !property.references.is_empty()
+        || property.rank.is_some()
+        || property.value_node.is_some())
+        && property.properties.is_empty()
+        && property.modifier.is_none()
+}
+
+/// Whether a property is a simple property of the direct namespace of a
+/// Wikibase instance, e.g. `wdt:P69`.
+pub fn is_direct(property: &Property) -> bool {
+    property.properties.is_empty()
+        && property.modifier.is_none()
+        && matches!(WikibaseFamily::resolve_property(property), Some((_, Namespace::Direct, _)))
+}
+
+/// Drops the statement node of a property if nothing but its value is used,
+/// i.e. it has no qualifiers, references, rank or value node and its
+/// statement variable is neither projected nor `used` elsewhere.
+pub fn to_truthy(property: &mut Property, used: &HashSet<String>) {
+    let unused = property.statement.as_ref().is_some_and(|statement| !used.contains(statement));
+    if unused
+        && !property.statement_selected_for_projection
+        && property.qualifiers.is_empty()
+        && property.references.is_empty()
+        && property.rank.is_none()
+        && property.value_node.is_none()
+    {
+        property.statement = None;
+    }
+}
+
+/// The prefixes used by the statement node pattern of a property.
+pub fn statement_prefixes(property: &Property) -> Vec<Prefix> {
+    let family = match WikibaseFamily::of_property(property) {
+        Some((family, _)) => family,
+        None => return vec![],
+    };
+    let mut prefixes = vec![family.prefix(Namespace::Claim), family.prefix(Namespace::Statement)];
+    for qualifier in &property.qualifiers {
+        let qualifier_family = WikibaseFamily::of_property(&qualifier.property)
+            .map(|(family, _)| family)
+            .unwrap_or_else(|| family.clone());
+        prefixes.push(qualifier_family.prefix(Namespace::Qualifier));
+        if qualifier.value_node.is_some() {
+            prefixes.push(qualifier_family.prefix(Namespace::QualifierValue));
+        }
+    }
+    if property.value_node.is_some() {
+        prefixes.push(family.prefix(Namespace::StatementValue));
+    }
+    let has_value_node =
+        property.value_node.is_some() || property.qualifiers.iter().any(|q| q.value_node.is_some());
+    if property.rank.is_some() || has_value_node {
+        prefixes.push(Prefix {
+            iri: ONTOLOGY_IRI.to_string(),
+            abbreviation: String::from("wikibase"),
+        });
+    }
+    if !property.references.is_empty() {
+        prefixes.push(Prefix {
+            iri: PROV_IRI.to_string(),
+            abbreviation: String::from("prov"),
+        });
+    }
+    for snak in property.references.iter().flat_map(|r| &r.snaks) {
+        let snak_family = WikibaseFamily::of_property(&snak.property)
+            .map(|(family, _)| family)
+            .unwrap_or_else(|| family.clone());
+        prefixes.push(snak_family.prefix(Namespace::Reference));
+    }
+    prefixes
+}
+
+/// Renders a property of a connection as statement node pattern
+///
+/// ```sparql
+/// ?person p:P69 ?statement1 .
+/// ?statement1 ps:P69 ?university .
+/// ?statement1 pq:P580 ?start .
+/// ```
+///
+/// or `None` if the property does not belong to a Wikibase instance.
+pub fn render_statement(connection: &Connection, property: &Property, indentation: &str) -> Option<String> {
+    let (family, local) = WikibaseFamily::of_property(property)?;
+    let statement = property.statement.as_deref()?;
+    let qualified = |namespace: Namespace, local: &str| format!("{}:{}", family.prefix(namespace).abbreviation, local);
+
+    // a statement with no value is of the `wdno:` class instead of having one
+    let (predicate, object) = if connection.target.snak_type == Some(SnakType::NoValue) {
+        (String::from("a"), qualified(Namespace::NoValue, &local))
+    } else {
+        (
+            qualified(Namespace::Statement, &local),
+            get_iri(&connection.target.id, &connection.target.prefix),
+        )
+    };
+    let mut result = format!(
+        "{}{} {} {} .\n{}{} {} {} .\n{}# {} -- [{}] -> {}\n",
+        indentation,
+        get_iri(&connection.source.id, &connection.source.prefix),
+        qualified(Namespace::Claim, &local),
+        statement,
+        indentation,
+        statement,
+        predicate,
+        object,
+        indentation,
+        connection.source.label,
+        property.label,
+        connection.target.label
+    );
+    if let Some(value_node) = &property.value_node {
+        result.push_str(&render_value_node(
+            &qualified(Namespace::StatementValue, &local),
+            statement,
+            value_node,
+            &property.label,
+            indentation,
+        ));
+    }
+    if let Some(rank) = property.rank {
+        result.push_str(&render_rank(statement, rank, indentation));
+    }
+    for qualifier in &property.qualifiers {
+        result.push_str(&render_snak(&family, statement, Namespace::Qualifier, qualifier, &property.label, indentation));
+    }
+    for reference in &property.references {
+        let variable = match reference.variable.as_deref() {
+            Some(variable) => variable,
+            None => continue,
+        };
+        result.push_str(&format!(
+            "{}{} prov:{} {} .\n",
+            indentation, statement, WAS_DERIVED_FROM, variable
+        ));
+        for snak in &reference.snaks {
+            result.push_str(&render_snak(&family, variable, Namespace::Reference, snak, "reference", indentation));
+        }
+    }
+    Some(result)
+}
+
+/// Renders a property to a "no value" target as class membership of the
+/// source, e.g. `?person a wdno:P40 .`, or `None` if the property does not
+/// belong to a Wikibase instance.
+pub fn render_no_value(connection: &Connection, property: &Property, indentation: &str) -> Option<String> {
+    let (family, local) = WikibaseFamily::of_property(property)?;
+    Some(format!(
+        "{}{} a {}:{} .\n{}# {} -- [{}] -> {}\n",
+        indentation,
+        get_iri(&connection.source.id, &connection.source.prefix),
+        family.prefix(Namespace::NoValue).abbreviation,
+        local,
+        indentation,
+        connection.source.label,
+        property.label,
+        connection.target.label
+    ))
+}
+
+/// The prefixes of a property to a "no value" target, or `None` if the
+/// property does not belong to a Wikibase instance.
+pub fn no_value_prefixes(property: &Property) -> Option<Vec<Prefix>> {
+    let (family, _) = WikibaseFamily::of_property(property)?;
+    let mut prefixes = vec![family.prefix(Namespace::NoValue)];
+    if is_statement(property) {
+        let statement = family.prefix(Namespace::Statement);
+        prefixes.extend(statement_prefixes(property).into_iter().filter(|prefix| *prefix != statement));
+    }
+    Some(prefixes)
+}
+
+/// The filter of an "unknown value" target, `isBlank(?value)`.
+pub fn some_value_filter(id: &str) -> Expression {
+    Expression::Function {
+        name: String::from("isBlank"),
+        arguments: vec![Expression::Variable { name: id.to_string() }],
+    }
+}
+
+/// The variable of the rank of a statement node, e.g. `?statement1Rank`.
+fn rank_variable(statement: &str) -> String {
+    format!("{}Rank", statement)
+}
+
+/// Renders the rank constraint of a statement node, e.g.
+/// `?statement1 wikibase:rank wikibase:PreferredRank .` The filter of a
+/// non-deprecated rank is part of the WHERE clause only, see `rank_filter`.
+fn render_rank(statement: &str, rank: Rank, indentation: &str) -> String {
+    let rank_iri = match rank {
+        Rank::Best => return format!("{}{} a wikibase:BestRank .\n", indentation, statement),
+        Rank::NonDeprecated => {
+            return format!("{}{} wikibase:rank {} .\n", indentation, statement, rank_variable(statement));
+        }
+        Rank::Preferred => "PreferredRank",
+        Rank::Normal => "NormalRank",
+        Rank::Deprecated => "DeprecatedRank",
+    };
+    format!("{}{} wikibase:rank wikibase:{} .\n", indentation, statement, rank_iri)
+}
+
+/// The filter of a statement of non-deprecated rank, e.g.
+/// `?statement1Rank != wikibase:DeprecatedRank`, which is rendered in the
+/// scope of the statement but not in a `CONSTRUCT` template.
+pub fn rank_filter(property: &Property) -> Option<Expression> {
+    WikibaseFamily::of_property(property)?;
+    let statement = property.statement.as_deref()?;
+    if !is_statement(property) || property.rank != Some(Rank::NonDeprecated) {
+        return None;
+    }
+    Some(Expression::Comparison {
+        operator: ComparisonOperator::NotEqual,
+        left: Box::new(Expression::Variable {
+            name: rank_variable(statement),
+        }),
+        right: Box::new(Expression::Term {
+            value: String::from("wikibase:DeprecatedRank"),
+        }),
+    })
+}
+
+/// The rank a triple of an imported statement node constrains, i.e.
+/// `a wikibase:BestRank`, `wikibase:rank wikibase:PreferredRank` or a rank
+/// variable filtered with `!= wikibase:DeprecatedRank`.
+fn rank_of(connection: &Connection) -> Option<Rank> {
+    let predicate = match connection.properties.as_slice() {
+        [property] if property.prefix.iri.is_empty() => property.id.as_str(),
+        _ => return None,
+    };
+    let target = &connection.target;
+    let ontology = |name: &str| format!("<{}{}>", ONTOLOGY_IRI, name);
+    if predicate == RDF_TYPE {
+        return Some(Rank::Best).filter(|_| target.id == ontology("BestRank"));
+    }
+    if predicate != ontology("rank") {
+        return None;
+    }
+    if target.id.starts_with('?') {
+        let excludes_deprecated = matches!(
+            target.filters.as_slice(),
+            [Expression::Comparison { operator: ComparisonOperator::NotEqual, left, right }]
+                if matches!(left.as_ref(), Expression::Variable { name } if *name == target.id)
+                    && matches!(right.as_ref(), Expression::Term { value } if *value == ontology("DeprecatedRank"))
+        );
+        return Some(Rank::NonDeprecated).filter(|_| excludes_deprecated);
+    }
+    [("PreferredRank", Rank::Preferred), ("NormalRank", Rank::Normal), ("DeprecatedRank", Rank::Deprecated)]
+        .iter()
+        .find(|(name, _)| target.id == ontology(name))
+        .map(|(_, rank)| *rank)
+}
+
+/// Renders a snak hanging off a statement or reference node, e.g.
+/// `?statement1 pq:P580 ?start .` with its label comment.
+fn render_snak(
+    family: &WikibaseFamily,
+    node: &str,
+    namespace: Namespace,
+    snak: &Snak,
+    node_label: &str,
+    indentation: &str,
+) -> String {
+    // a snak without a namespace of its own is taken from the edge
+    let (snak_family, snak_local) = WikibaseFamily::of_property(&snak.property)
+        .unwrap_or_else(|| (family.clone(), snak.property.id.clone()));
+    let mut result = format!(
+        "{}{} {}:{} {} .\n{}# {} -- [{}] -> {}\n",
+        indentation,
+        node,
+        snak_family.prefix(namespace).abbreviation,
+        snak_local,
+        get_iri(&snak.value.id, &snak.value.prefix),
+        indentation,
+        node_label,
+        snak.property.label,
+        snak.value.label
+    );
+    if let (Some(value_node), Namespace::Qualifier) = (&snak.value_node, namespace) {
+        let predicate = format!("{}:{}", snak_family.prefix(Namespace::QualifierValue).abbreviation, snak_local);
+        result.push_str(&render_value_node(&predicate, node, value_node, &snak.property.label, indentation));
+    }
+    result
+}
+
+/// Renders the full value node of a statement or qualifier, e.g.
+///
+/// ```sparql
+/// ?statement1 psv:P569 ?value1 .
+/// ?value1 wikibase:timePrecision ?precision .
+/// ```
+fn render_value_node(predicate: &str, node: &str, value_node: &ValueNode, label: &str, indentation: &str) -> String {
+    let variable = match value_node.variable.as_deref() {
+        Some(variable) => variable,
+        None => return String::new(),
+    };
+    let mut result = format!("{}{} {} {} .\n", indentation, node, predicate, variable);
+    for component in &value_node.components {
+        let (_, name) = VALUE_KINDS.iter().find(|(kind, _)| *kind == component.kind).unwrap();
+        result.push_str(&format!(
+            "{}{} wikibase:{} {} .\n{}# {} -- [{}] -> {}\n",
+            indentation,
+            variable,
+            name,
+            get_iri(&component.value.id, &component.value.prefix),
+            indentation,
+            label,
+            name,
+            component.value.label
+        ));
+    }
+    result
+}
+
+/// Folds the statement node patterns of a graph and its union branches back
+/// into qualified edges, see `fold_connections`, and recognises "no value" and
+/// "unknown value" targets, see `fold_no_values` and `mark_some_values`.
+pub fn fold_statements(graph: &mut QueryGraph) {
+    fn fold(connections: &mut Vec<Connection>, unions: &mut [UnionGroup], taken: &mut HashSet<String>) {
+        fold_no_values(connections, taken);
+        fold_connections(connections, taken);
+        mark_some_values(connections);
+        for branch in unions.iter_mut().flat_map(|u| &mut u.branches) {
+            fold(&mut branch.connections, &mut branch.unions, taken);
+        }
+    }
+
+    let mut taken: HashSet<String> = graph
+        .all_connections()
+        .iter()
+        .flat_map(|c| vec![c.source.id.clone(), c.target.id.clone()])
+        .collect();
+    fold(&mut graph.connections, &mut graph.unions, &mut taken);
+}
+
+/// A fresh variable `?{stem}1`, `?{stem}2`, ... which is not `taken` yet.
+fn fresh_variable(stem: &str, taken: &mut HashSet<String>) -> String {
+    let mut count = 0;
+    loop {
+        count += 1;
+        let name = format!("?{}{}", stem, count);
+        if taken.insert(name.clone()) {
+            break name;
+        }
+    }
+}
+
+/// An entity for a special value of a statement.
+fn special_entity(id: String, label: &str, snak_type: SnakType) -> Entity {
+    Entity {
+        id,
+        label: label.to_string(),
+        prefix: Prefix {
+            iri: String::new(),
+            abbreviation: String::new(),
+        },
+        selected_for_projection: snak_type == SnakType::SomeValue,
+        distinct: false,
+        filters: vec![],
+        aggregates: vec![],
+        values: vec![],
+        snak_type: Some(snak_type),
+        companions: Companions::default(),
+    }
+}
+
+/// Turns `?person a wdno:P40` into an edge `wdt:P40` to a "no value" target.
+/// On a statement node, i.e. the target of a `p:P40` triple, the edge is a
+/// `ps:P40` triple instead, so that `fold_connections` folds it.
+fn fold_no_values(connections: &mut [Connection], taken: &mut HashSet<String>) {
+    for index in 0..connections.len() {
+        let connection = &connections[index];
+        let is_type = matches!(
+            connection.properties.as_slice(),
+            [property] if property.prefix.iri.is_empty() && property.id == RDF_TYPE
+        );
+        if !is_type || !connection.target.id.starts_with('<') {
+            continue;
+        }
+        let iri = connection.target.id.trim_start_matches('<').trim_end_matches('>');
+        let (family, local) = match WikibaseFamily::resolve(iri, None) {
+            Some((family, Namespace::NoValue, local)) => (family, local),
+            _ => continue,
+        };
+        let on_statement = connections.iter().any(|claim| {
+            claim.target.id == connection.source.id
+                && matches!(resolve_connection(claim), Some((f, Namespace::Claim, l)) if f.base == family.base && l == local)
+        });
+
+        let mut property = direct_property(&family, &local);
+        if on_statement {
+            property.id = format!("<{}{}>", family.prefix(Namespace::Statement).iri, local);
+        }
+        let connection = &mut connections[index];
+        connection.properties = vec![property];
+        connection.target = special_entity(fresh_variable("noValue", taken), "no value", SnakType::NoValue);
+    }
+}
+
+/// Marks the targets of Wikibase properties as "unknown value" if they are
+/// filtered with `isBlank`. A blank node target without the filter is an
+/// existential variable, e.g. `?item wdt:P570 []` for "has any date of
+/// death", and stays an ordinary node.
+fn mark_some_values(connections: &mut [Connection]) {
+    for connection in connections.iter_mut() {
+        let wikibase = match connection.properties.as_slice() {
+            [property] => WikibaseFamily::of_property(property).is_some(),
+            _ => false,
+        };
+        let target = &mut connection.target;
+        if !wikibase || target.snak_type.is_some() {
+            continue;
+        }
+        let filter = some_value_filter(&target.id);
+        let is_blank = |f: &Expression| match (f, &filter) {
+            (Expression::Function { name, arguments }, Expression::Function { arguments: expected, .. }) => {
+                name.eq_ignore_ascii_case("isBlank") && arguments == expected
+            }
+            _ => false,
+        };
+        if target.filters.iter().any(is_blank) {
+            target.filters.retain(|f| !is_blank(f));
+            target.snak_type = Some(SnakType::SomeValue);
+        }
+    }
+}
+
+/// Whether two connections are rendered into the same block.
+fn same_block(a: &Connection, b: &Connection) -> bool {
+    a.optional == b.optional && a.group == b.group && a.negation == b.negation
+}
+
+/// The single property of an imported connection with its namespace.
+fn resolve_connection(connection: &Connection) -> Option<(WikibaseFamily, Namespace, String)> {
+    match connection.properties.as_slice() {
+        [property] if property.properties.is_empty() && property.modifier.is_none() => {
+            WikibaseFamily::resolve_property(property)
+        }
+        _ => None,
+    }
+}
+
+/// Whether an imported connection is a `prov:wasDerivedFrom` triple to a
+/// variable or blank node.
+fn is_derivation(connection: &Connection) -> bool {
+    let node = &connection.target.id;
+    match connection.properties.as_slice() {
+        [property] => {
+            let iri = format!("{}{}", property.prefix.iri, property.id);
+            (iri == format!("<{}{}>", PROV_IRI, WAS_DERIVED_FROM) || iri == format!("{}{}", PROV_IRI, WAS_DERIVED_FROM))
+                && (node.starts_with('?') || node.starts_with("_:"))
+        }
+        _ => false,
+    }
+}
+
+/// Whether an entity id is a variable or a blank node, i.e. a node the folded
+/// pattern can hang off.
+fn is_node(id: &str) -> bool {
+    id.starts_with('?') || id.starts_with("_:")
+}
+
+/// Whether an entity id is the IRI of a concrete statement, e.g. `wds:Q42-...`.
+fn is_statement_iri(id: &str) -> bool {
+    id.starts_with('<')
+        && matches!(
+            WikibaseFamily::resolve(id.trim_start_matches('<').trim_end_matches('>'), None),
+            Some((_, Namespace::StatementNode, _))
+        )
+}
+
+/// Folds the `wikibase:timeValue`, `wikibase:quantityUnit`, ... triples of an
+/// imported value node, in the block of `block`. Returns the value node and
+/// the indices of its triples.
+fn fold_value_node(connections: &[Connection], node: &str, block: &Connection) -> (ValueNode, Vec<usize>) {
+    let mut indices = vec![];
+    let mut components = vec![];
+    for (i, connection) in connections.iter().enumerate() {
+        if connection.source.id != node || !same_block(connection, block) {
+            continue;
+        }
+        let predicate = match connection.properties.as_slice() {
+            [property] if property.prefix.iri.is_empty() => property.id.as_str(),
+            _ => continue,
+        };
+        let kind = VALUE_KINDS
+            .iter()
+            .find(|(_, name)| predicate == format!("<{}{}>", ONTOLOGY_IRI, name))
+            .map(|(kind, _)| *kind);
+        if let Some(kind) = kind {
+            indices.push(i);
+            components.push(ValueComponent {
+                kind,
+                value: connection.target.clone(),
+            });
+        }
+    }
+    let value_node = ValueNode {
+        variable: Some(node.to_string()).filter(|node| node.starts_with('?')),
+        components,
+    };
+    (value_node, indices)
+}
+
+/// A property of the direct namespace, as the generator expects it.
+fn direct_property(family: &WikibaseFamily, local: &str) -> Property {
+    let id = format!("<{}{}>", family.prefix(Namespace::Direct).iri, local);
+    Property {
+        id: id.clone(),
+        label: id,
+        prefix: Prefix {
+            iri: String::new(),
+            abbreviation: String::new(),
+        },
+        selected_for_projection: true,
+        properties: vec![],
+        path_type: None,
+        modifier: None,
+        optional: false,
+        qualifiers: vec![],
+        statement: None,
+        statement_selected_for_projection: false,
+        references: vec![],
+        rank: None,
+        value_node: None,
+    }
+}
+
+/// Collapses `?item p:P69 ?st . ?st ps:P69 ?uni . ?st pq:P580 ?start` into one
+/// edge from `?item` to `?uni` with the statement variable `?st` and the
+/// qualifier `P580`. The statement node may also be the IRI of a concrete
+/// statement. A blank statement node is given a fresh `?statement1`,
+/// `?statement2`, ... which is not `taken` yet. `prov:wasDerivedFrom` triples
+/// of the statement node become references with the `pr:` triples of their
+/// reference node; a blank reference node keeps no variable. A single rank
+/// triple becomes the rank of the edge. `psv:` and `pqv:` triples become the
+/// value nodes of the edge and of its qualifiers. All triples have to be in
+/// the same block.
+fn fold_connections(connections: &mut Vec<Connection>, taken: &mut HashSet<String>) {
+    let mut index = 0;
+    while index < connections.len() {
+        let claim = &connections[index];
+        let statement_node = claim.target.id.clone();
+        let (family, local) = match resolve_connection(claim) {
+            Some((family, Namespace::Claim, local)) if is_node(&statement_node) || is_statement_iri(&statement_node) => {
+                (family, local)
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let hangs_off = |connection: &Connection, node: &str, namespace: Namespace| -> Option<String> {
+            if connection.source.id != node || !same_block(connection, claim) {
+                return None;
+            }
+            match resolve_connection(connection) {
+                Some((f, n, local)) if n == namespace && f.base == family.base => Some(local),
+                _ => None,
+            }
+        };
+        let statements: Vec<usize> = (0..connections.len())
+            .filter(|&i| hangs_off(&connections[i], &statement_node, Namespace::Statement).as_deref() == Some(local.as_str()))
+            .collect();
+        let qualifiers: Vec<(usize, String)> = (0..connections.len())
+            .filter_map(|i| hangs_off(&connections[i], &statement_node, Namespace::Qualifier).map(|local| (i, local)))
+            .collect();
+        let derivations: Vec<(usize, Vec<(usize, String)>)> = (0..connections.len())
+            .filter(|&i| {
+                let connection = &connections[i];
+                connection.source.id == statement_node && same_block(connection, claim) && is_derivation(connection)
+            })
+            .map(|i| {
+                let node = &connections[i].target.id;
+                let snaks = (0..connections.len())
+                    .filter_map(|j| hangs_off(&connections[j], node, Namespace::Reference).map(|local| (j, local)))
+                    .collect();
+                (i, snaks)
+            })
+            .collect();
+        // the first `psv:` or `pqv:` triple of the property with that local name
+        let value_link = |namespace: Namespace, local: &str| {
+            (0..connections.len()).find(|&i| {
+                is_node(&connections[i].target.id)
+                    && hangs_off(&connections[i], &statement_node, namespace).as_deref() == Some(local)
+            })
+        };
+        let statement_value = value_link(Namespace::StatementValue, &local);
+        let qualifier_values: Vec<Option<usize>> = qualifiers
+            .iter()
+            .map(|(_, local)| value_link(Namespace::QualifierValue, local))
+            .collect();
+        let ranks: Vec<(usize, Rank)> = (0..connections.len())
+            .filter(|&i| connections[i].source.id == statement_node && same_block(&connections[i], claim))
+            .filter_map(|i| rank_of(&connections[i]).map(|rank| (i, rank)))
+            .collect();
+        let rank = match ranks.as_slice() {
+            [(i, rank)] => Some((*i, *rank)),
+            _ => None,
+        };
+        let statement = match statements.as_slice() {
+            [statement] => *statement,
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let statement_variable = if statement_node.starts_with('?') || is_statement_iri(&statement_node) {
+            statement_node.clone()
+        } else {
+            fresh_variable("statement", taken)
+        };
+
+        let mut removed: Vec<usize> = vec![];
+        let value_node = |link: Option<usize>, removed: &mut Vec<usize>| {
+            link.map(|i| {
+                let (value_node, indices) = fold_value_node(connections, &connections[i].target.id, &connections[i]);
+                removed.push(i);
+                removed.extend(indices);
+                value_node
+            })
+        };
+
+        let mut property = direct_property(&family, &local);
+        property.statement = Some(statement_variable.clone());
+        property.value_node = value_node(statement_value, &mut removed);
+        property.qualifiers = qualifiers
+            .iter()
+            .zip(&qualifier_values)
+            .map(|((i, local), link)| Snak {
+                property: direct_property(&family, local),
+                value: connections[*i].target.clone(),
+                value_node: value_node(*link, &mut removed),
+            })
+            .collect();
+        property.rank = rank.map(|(_, rank)| rank);
+        property.references = derivations
+            .iter()
+            .map(|(i, snaks)| {
+                let node = &connections[*i].target.id;
+                Reference {
+                    variable: Some(node.clone()).filter(|node| node.starts_with('?')),
+                    snaks: snaks
+                        .iter()
+                        .map(|(j, local)| Snak {
+                            property: direct_property(&family, local),
+                            value: connections[*j].target.clone(),
+                            value_node: None,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        // the claim keeps its source and block, and takes over the value
+        let target = connections[statement].target.clone();
+        removed.extend(qualifiers.iter().map(|(i, _)| *i));
+        for (i, snaks) in &derivations {
+            removed.push(*i);
+            removed.extend(snaks.iter().map(|(j, _)| *j));
+        }
+        removed.extend(rank.map(|(i, _)| i));
+        removed.push(statement);
+        removed.sort_unstable();
+        removed.dedup();
+        let (mut filters, mut computed) = (vec![], vec![]);
+        for &i in &removed {
+            filters.append(&mut connections[i].filters);
+            computed.append(&mut connections[i].computed);
+        }
+        let claim = &mut connections[index];
+        claim.target = target;
+        claim.properties = vec![property];
+        claim.filters.extend(filters);
+        claim.computed.extend(computed);
+        // triples which could not be folded keep hanging off the statement
+        for connection in connections.iter_mut().filter(|c| c.source.id == statement_node) {
+            connection.source.id = statement_variable.clone();
+        }
+        for i in removed.into_iter().rev() {
+            connections.remove(i);
+            if i < index {
+                index -= 1;
+            }
+        }
+        index += 1;
+    }
+}
@@ -0,0 +1,52 @@
+//! Helpers and fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use spargebra::Query;
+
+pub fn assert_parses(query: &str) {
+    assert!(
+        Query::parse(query, None).is_ok(),
+        "Generated query should parse:\n{}",
+        query
+    );
+}
+
+pub fn parse_graph_json(json: &str) -> Value {
+    let value: Value =
+        serde_json::from_str(json).unwrap_or_else(|e| panic!("Invalid JSON output:\n{}\n{:?}", json, e));
+    assert!(value.is_object(), "Expected JSON object, got: {}", value);
+    value
+}
+
+pub fn parse_connections_json(json: &str) -> Vec<Value> {
+    let value: Value =
+        serde_json::from_str(json).unwrap_or_else(|e| panic!("Invalid JSON output:\n{}\n{:?}", json, e));
+
+    value
+        .as_array()
+        .unwrap_or_else(|| panic!("Expected JSON array, got: {}", value))
+        .clone()
+}
+
+pub fn wdt(id: &str, label: &str) -> Value {
+    json!({ "id": id, "label": label, "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } })
+}
+
+pub fn variable(id: &str, label: &str) -> Value {
+    json!({ "id": id, "label": label, "prefix": { "iri": "", "abbreviation": "" } })
+}
+
+/// `?person wdt:P69 ?university`
+pub fn educated_at() -> Value {
+    educated_at_with(wdt("P69", "educated at"))
+}
+
+/// `?person ... ?university` with the given property.
+pub fn educated_at_with(property: Value) -> Value {
+    json!([{
+        "source": variable("?person", "person"),
+        "target": variable("?university", "university"),
+        "properties": [property]
+    }])
+}
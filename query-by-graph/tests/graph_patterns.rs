@@ -0,0 +1,987 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, query_to_vqg_wasm, vqg_to_query_wasm};
+use serde_json::{json, Value};
+use spargebra::Query;
+use common::{assert_parses, parse_graph_json, parse_connections_json};
+
+fn human_with_date_of_death(connection_optional: bool, property_optional: bool) -> Value {
+    json!([
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "Q5", "label": "Human", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" }, "selectedForProjection": false },
+            "properties": [
+                { "id": "P31", "label": "instance of", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        },
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?death", "label": "date of death", "prefix": { "iri": "", "abbreviation": "" } },
+            "optional": connection_optional,
+            "properties": [
+                { "id": "P570", "label": "date of death", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" }, "optional": property_optional }
+            ]
+        }
+    ])
+}
+
+#[test]
+fn test_optional_connection_generates_optional_block() {
+    let query = vqg_to_query_wasm(&human_with_date_of_death(true, false).to_string(), false, false);
+
+    assert!(query.contains("    ?human wdt:P31 wd:Q5 ."));
+    assert!(query.contains("    OPTIONAL {\n        ?human wdt:P570 ?death .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_optional_property_generates_optional_block() {
+    let query = vqg_to_query_wasm(&human_with_date_of_death(false, true).to_string(), false, false);
+
+    assert!(query.contains("    OPTIONAL {\n        ?human wdt:P570 ?death .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_optional_blocks_come_after_mandatory_triples() {
+    let mut graph = human_with_date_of_death(true, false);
+    graph.as_array_mut().unwrap().reverse();
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    let mandatory = query.find("?human wdt:P31 wd:Q5").unwrap();
+    let optional = query.find("OPTIONAL").unwrap();
+    assert!(mandatory < optional, "{}", query);
+}
+
+#[test]
+fn test_optional_group_generates_single_block() {
+    let graph = json!([
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?death", "label": "date of death", "prefix": { "iri": "", "abbreviation": "" } },
+            "optional": true,
+            "group": "death",
+            "properties": [
+                { "id": "P570", "label": "date of death", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        },
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?place", "label": "place of death", "prefix": { "iri": "", "abbreviation": "" } },
+            "optional": true,
+            "group": "death",
+            "properties": [
+                { "id": "P20", "label": "place of death", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }
+    ]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert_eq!(query.matches("OPTIONAL").count(), 1, "{}", query);
+    assert!(query.contains("?human wdt:P570 ?death ."));
+    assert!(query.contains("?human wdt:P20 ?place ."));
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_optional_single_triple() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human ?death WHERE {
+    ?human wdt:P31 wd:Q5 .
+    OPTIONAL { ?human wdt:P570 ?death . }
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[0]["optional"], Value::Bool(false));
+    assert_eq!(connections[1]["optional"], Value::Bool(true));
+    assert_eq!(connections[1]["group"], Value::Null);
+    assert_eq!(connections[1]["target"]["id"], Value::String("?death".to_string()));
+}
+
+#[test]
+fn test_import_optional_block_with_several_triples() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human ?death ?place WHERE {
+    ?human wdt:P31 wd:Q5 .
+    OPTIONAL { ?human wdt:P570 ?death . ?human wdt:P20 ?place . }
+    OPTIONAL { ?human wdt:P569 ?birth . ?human wdt:P19 ?birthPlace . }
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 5);
+    assert_eq!(connections[0]["optional"], Value::Bool(false));
+    for c in &connections[1..] {
+        assert_eq!(c["optional"], Value::Bool(true));
+    }
+    assert_eq!(connections[1]["group"], connections[2]["group"]);
+    assert_eq!(connections[3]["group"], connections[4]["group"]);
+    assert_ne!(connections[1]["group"], connections[3]["group"]);
+    assert!(connections[1]["group"].is_string());
+}
+
+#[test]
+fn test_optional_round_trip() {
+    let query = vqg_to_query_wasm(&human_with_date_of_death(true, false).to_string(), false, false);
+    let connections = parse_connections_json(&query_to_vqg_wasm(&query));
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[1]["optional"], Value::Bool(true));
+
+    let regenerated = vqg_to_query_wasm(&Value::Array(connections).to_string(), false, false);
+    assert!(regenerated.contains("OPTIONAL {"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+fn date_of_death_filter() -> Value {
+    json!({
+        "type": "comparison",
+        "operator": ">",
+        "left": { "type": "variable", "name": "?death" },
+        "right": { "type": "term", "value": "\"1800-01-01\"^^xsd:dateTime" }
+    })
+}
+
+#[test]
+fn test_entity_filter_generates_filter() {
+    let mut graph = human_with_date_of_death(false, false);
+    graph[1]["target"]["filters"] = json!([date_of_death_filter()]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("    FILTER(?death > \"1800-01-01\"^^xsd:dateTime)\n"), "{}", query);
+    assert!(query.contains("PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>"));
+    assert_parses(&query);
+}
+
+#[test]
+fn test_entity_filter_is_rendered_once_per_entity() {
+    let mut graph = human_with_date_of_death(false, false);
+    let filter = json!({
+        "type": "function",
+        "name": "CONTAINS",
+        "arguments": [
+            { "type": "function", "name": "STR", "arguments": [{ "type": "variable", "name": "?human" }] },
+            { "type": "term", "value": "\"Q\"" }
+        ]
+    });
+    graph[0]["source"]["filters"] = json!([filter]);
+    graph[1]["source"]["filters"] = json!([filter]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert_eq!(query.matches("FILTER(CONTAINS(STR(?human), \"Q\"))").count(), 1, "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_entity_filter_of_optional_entity_stays_in_optional_block() {
+    let mut graph = human_with_date_of_death(true, false);
+    graph[1]["target"]["filters"] = json!([date_of_death_filter()]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(
+        query.contains("        FILTER(?death > \"1800-01-01\"^^xsd:dateTime)\n    }"),
+        "{}",
+        query
+    );
+    assert_parses(&query);
+}
+
+#[test]
+fn test_graph_filter_with_boolean_connectives_and_in_list() {
+    let graph = json!({
+        "connections": human_with_date_of_death(false, false),
+        "filters": [
+            {
+                "type": "or",
+                "operands": [
+                    { "type": "comparison", "operator": "!=", "left": { "type": "variable", "name": "?human" }, "right": { "type": "variable", "name": "?death" } },
+                    {
+                        "type": "and",
+                        "operands": [
+                            { "type": "not", "operand": { "type": "function", "name": "BOUND", "arguments": [{ "type": "variable", "name": "?death" }] } },
+                            { "type": "in", "operand": { "type": "variable", "name": "?human" }, "values": [{ "type": "term", "value": "wd:Q1" }, { "type": "term", "value": "wd:Q2" }], "negated": true }
+                        ]
+                    }
+                ]
+            }
+        ]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(
+        query.contains("FILTER(?human != ?death || !BOUND(?death) && ?human NOT IN (wd:Q1, wd:Q2))"),
+        "{}",
+        query
+    );
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_filter_attaches_to_entity() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+PREFIX xsd: <http://www.w3.org/2001/XMLSchema#>
+SELECT ?human ?death WHERE {
+    ?human wdt:P31 wd:Q5 .
+    ?human wdt:P570 ?death .
+    FILTER(?death > "1800-01-01"^^xsd:dateTime && LANG(?death) = "de")
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[1]["target"]["filters"][0], date_of_death_filter());
+    assert_eq!(
+        connections[1]["target"]["filters"][1],
+        json!({
+            "type": "comparison",
+            "operator": "=",
+            "left": { "type": "function", "name": "LANG", "arguments": [{ "type": "variable", "name": "?death" }] },
+            "right": { "type": "term", "value": "\"de\"" }
+        })
+    );
+}
+
+#[test]
+fn test_import_filter_on_several_variables_becomes_graph_filter() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?a ?b WHERE {
+    ?a wdt:P26 ?b .
+    FILTER(?a != ?b && CONTAINS(STR(?a), "Q"))
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 1);
+    assert_eq!(graph["filters"].as_array().unwrap().len(), 1);
+    assert_eq!(graph["filters"][0]["operator"], "!=");
+    assert_eq!(graph["connections"][0]["source"]["filters"][0]["name"], "CONTAINS");
+}
+
+#[test]
+fn test_import_filter_inside_optional() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human ?label WHERE {
+    ?human wdt:P31 ?class .
+    OPTIONAL { ?human <http://www.w3.org/2000/01/rdf-schema#label> ?label . FILTER(LANG(?label) IN ("de", "en")) }
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[1]["optional"], Value::Bool(true));
+    assert_eq!(connections[1]["target"]["filters"][0]["type"], "in");
+
+    let regenerated = vqg_to_query_wasm(&Value::Array(connections).to_string(), false, false);
+    assert!(
+        regenerated.contains("        FILTER(LANG(?label) IN (\"de\", \"en\"))\n    }"),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_filter_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human ?birth WHERE {
+    ?human wdt:P569 ?birth .
+    FILTER(YEAR(?birth) >= 1749 && (REGEX(STR(?human), "Q1") || STRSTARTS(STR(?human), "http")))
+}"###;
+
+    let graph = query_to_graph_wasm(query);
+    let regenerated = vqg_to_query_wasm(&graph, false, false);
+
+    assert_parses(&regenerated);
+    assert!(regenerated.contains("FILTER(YEAR(?birth) >= 1749)"), "{}", regenerated);
+    assert!(
+        regenerated.contains("FILTER(REGEX(STR(?human), \"Q1\") || STRSTARTS(STR(?human), \"http\"))"),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_filter_scope_round_trip() {
+    for query in [
+        // humans without a date of death
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    OPTIONAL { ?human wdt:P570 ?death . }
+    FILTER(!BOUND(?death))
+}"###,
+        // only the date of death of Q1 is left out
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?death ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    OPTIONAL { ?human wdt:P570 ?death . FILTER(?human != wd:Q1) }
+}"###,
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?birth ?death ?human WHERE {
+    ?human wdt:P569 ?birth .
+    OPTIONAL { ?human wdt:P570 ?death . FILTER(?death > ?birth) }
+}"###,
+    ] {
+        let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+        assert_eq!(
+            Query::parse(&regenerated, None).unwrap().to_string(),
+            Query::parse(query, None).unwrap().to_string(),
+            "{}",
+            regenerated
+        );
+    }
+}
+
+#[test]
+fn test_entity_filter_is_rendered_in_the_scope_of_its_connection() {
+    let mut graph = human_with_date_of_death(true, false);
+    let filter = json!({
+        "type": "comparison",
+        "operator": "!=",
+        "left": { "type": "variable", "name": "?human" },
+        "right": { "type": "term", "value": "wd:Q1" }
+    });
+    // ?human occurs in the mandatory connection, too
+    graph[1]["source"]["filters"] = json!([filter]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("        FILTER(?human != wd:Q1)\n    }"), "{}", query);
+    assert_parses(&query);
+}
+
+fn place_connection(property: &str, label: &str) -> Value {
+    json!({
+        "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+        "target": { "id": "Q2079", "label": "Leipzig", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" } },
+        "properties": [
+            { "id": property, "label": label, "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+        ]
+    })
+}
+
+#[test]
+fn test_union_group_generates_union() {
+    let graph = json!({
+        "connections": [],
+        "unions": [
+            {
+                "branches": [
+                    { "connections": [place_connection("P19", "place of birth")] },
+                    { "connections": [place_connection("P20", "place of death")] }
+                ]
+            }
+        ]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(
+        query.contains("    {\n        ?human wdt:P19 wd:Q2079 .\n"),
+        "{}",
+        query
+    );
+    assert!(query.contains("    } UNION {\n        ?human wdt:P20 wd:Q2079 .\n"), "{}", query);
+    assert!(query.contains("SELECT ?human WHERE"), "{}", query);
+    assert!(query.contains("PREFIX wdt: <http://www.wikidata.org/prop/direct/>"));
+    assert_parses(&query);
+}
+
+#[test]
+fn test_union_branches_with_several_triples_and_filters() {
+    let graph = json!({
+        "connections": [
+            {
+                "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+                "target": { "id": "Q5", "label": "Human", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" } },
+                "properties": [
+                    { "id": "P31", "label": "instance of", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+                ]
+            }
+        ],
+        "unions": [
+            {
+                "branches": [
+                    { "connections": [place_connection("P19", "place of birth"), place_connection("P69", "educated at")] },
+                    {
+                        "connections": [place_connection("P20", "place of death")],
+                        "filters": [{ "type": "function", "name": "isIRI", "arguments": [{ "type": "variable", "name": "?human" }] }]
+                    }
+                ]
+            }
+        ]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("        FILTER(isIRI(?human))\n    }"), "{}", query);
+    let parsed = Query::parse(&query, None).unwrap().to_string();
+    assert_eq!(parsed.matches("UNION").count(), 1, "{}", parsed);
+}
+
+#[test]
+fn test_import_union() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    { ?human wdt:P19 wd:Q2079 . ?human wdt:P69 ?uni . }
+    UNION
+    { ?human wdt:P20 wd:Q2079 . FILTER(?human != wd:Q5879) }
+    UNION
+    { ?human wdt:P551 wd:Q2079 . }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 1);
+    let branches = graph["unions"][0]["branches"].as_array().unwrap();
+    assert_eq!(branches.len(), 3);
+    assert_eq!(branches[0]["connections"].as_array().unwrap().len(), 2);
+    assert_eq!(branches[1]["connections"][0]["source"]["filters"][0]["operator"], "!=");
+    assert_eq!(branches[2]["connections"].as_array().unwrap().len(), 1);
+
+    // only projected variables stay selected, also inside the branches
+    assert_eq!(branches[0]["connections"][1]["target"]["selectedForProjection"], Value::Bool(false));
+}
+
+#[test]
+fn test_union_round_trip() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    { ?human wdt:P19 wd:Q2079 . } UNION { ?human wdt:P20 wd:Q2079 . }
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
+
+fn human_without_date_of_death(negation: &str) -> Value {
+    let mut graph = human_with_date_of_death(false, false);
+    graph[1]["negation"] = json!(negation);
+    graph[1]["target"]["selectedForProjection"] = json!(false);
+    graph
+}
+
+#[test]
+fn test_negated_connection_generates_filter_not_exists() {
+    let query = vqg_to_query_wasm(&human_without_date_of_death("notExists").to_string(), false, false);
+
+    assert!(
+        query.contains("    FILTER NOT EXISTS {\n        ?human wdt:P570 ?death .\n"),
+        "{}",
+        query
+    );
+    assert!(query.contains("SELECT ?human WHERE"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_negated_connection_generates_minus() {
+    let query = vqg_to_query_wasm(&human_without_date_of_death("minus").to_string(), false, false);
+
+    assert!(query.contains("    MINUS {\n        ?human wdt:P570 ?death .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_negated_group_generates_single_block_after_optional_blocks() {
+    let mut graph = human_with_date_of_death(false, false);
+    let connections = graph.as_array_mut().unwrap();
+    let mut negated = place_connection("P20", "place of death");
+    negated["negation"] = json!("notExists");
+    negated["group"] = json!("dead");
+    connections[1]["negation"] = json!("notExists");
+    connections[1]["group"] = json!("dead");
+    connections.insert(0, negated);
+    let mut optional = place_connection("P19", "place of birth");
+    optional["optional"] = json!(true);
+    connections.push(optional);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert_eq!(query.matches("FILTER NOT EXISTS").count(), 1, "{}", query);
+    assert!(query.find("OPTIONAL").unwrap() < query.find("FILTER NOT EXISTS").unwrap(), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_minus() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    MINUS { ?human wdt:P570 ?death . }
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[0]["negation"], Value::Null);
+    assert_eq!(connections[1]["negation"], "minus");
+    assert_eq!(connections[1]["group"], Value::Null);
+}
+
+#[test]
+fn test_import_filter_not_exists_with_several_triples() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    FILTER NOT EXISTS { ?human wdt:P570 ?death . ?human wdt:P20 ?place . }
+    FILTER(?human != wd:Q5879)
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 3);
+    assert_eq!(connections[1]["negation"], "notExists");
+    assert_eq!(connections[2]["negation"], "notExists");
+    assert!(connections[1]["group"].is_string());
+    assert_eq!(connections[1]["group"], connections[2]["group"]);
+    assert_eq!(connections[0]["source"]["filters"][0]["operator"], "!=");
+}
+
+#[test]
+fn test_import_refuses_blocks_which_cannot_be_kept_in_a_negation() {
+    let queries = [
+        // a union inside MINUS would become a mandatory union
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?h WHERE {
+    ?h wdt:P31 ?class .
+    MINUS { { ?h wdt:P19 ?a } UNION { ?h wdt:P20 ?a } }
+}"###,
+        // a NOT EXISTS inside OPTIONAL would be moved to the top level
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?h ?a WHERE {
+    ?h wdt:P31 wd:Q5 .
+    OPTIONAL { ?h wdt:P570 ?a . FILTER NOT EXISTS { ?a wdt:P31 wd:Q1 } }
+}"###,
+    ];
+
+    for query in queries {
+        let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+        assert_eq!(graph["connections"], json!([]), "{}", query);
+        assert_eq!(graph["unions"], json!([]), "{}", query);
+    }
+}
+
+#[test]
+fn test_import_refuses_nested_optional_blocks() {
+    let queries = [
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?h ?a ?b WHERE {
+    ?h wdt:P31 ?class .
+    OPTIONAL { ?h wdt:P570 ?a . OPTIONAL { ?a wdt:P1 ?b } }
+}"###,
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?h WHERE {
+    ?h wdt:P31 ?class .
+    FILTER NOT EXISTS { ?h wdt:P570 ?a . OPTIONAL { ?a wdt:P1 ?b } }
+}"###,
+    ];
+
+    for query in queries {
+        let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+        assert_eq!(graph["connections"], json!([]), "{}", query);
+    }
+}
+
+#[test]
+fn test_import_refuses_union_inside_optional() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?h ?place WHERE {
+    ?h wdt:P31 ?class .
+    OPTIONAL { { ?h wdt:P19 ?place } UNION { ?h wdt:P20 ?place } }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"], json!([]));
+    assert_eq!(graph["unions"], json!([]));
+}
+
+#[test]
+fn test_negation_round_trip() {
+    for query in [
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    FILTER NOT EXISTS { ?human wdt:P570 ?death . }
+}"###,
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    MINUS { ?human wdt:P570 ?death . }
+}"###,
+        // filters inside a negated block stay in that block
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P31 wd:Q5 .
+    FILTER NOT EXISTS { ?human wdt:P570 ?death . FILTER(?human != wd:Q1) }
+}"###,
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?human WHERE {
+    ?human wdt:P569 ?birth .
+    MINUS { ?human wdt:P570 ?death . FILTER(?death < ?birth) }
+}"###,
+    ] {
+        let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+        assert_eq!(
+            Query::parse(&regenerated, None).unwrap().to_string(),
+            Query::parse(query, None).unwrap().to_string(),
+            "{}",
+            regenerated
+        );
+    }
+}
+
+#[test]
+fn test_entity_values_generate_values_block() {
+    let graph = json!([
+        {
+            "source": {
+                "id": "?item", "label": "item", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" },
+                "values": ["Q5879", "Q1339", "<http://www.wikidata.org/entity/Q254>"]
+            },
+            "target": { "id": "?birth", "label": "date of birth", "prefix": { "iri": "", "abbreviation": "" } },
+            "properties": [
+                { "id": "P569", "label": "date of birth", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        },
+        {
+            "source": {
+                "id": "?item", "label": "item", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" },
+                "values": ["Q5879", "Q1339", "<http://www.wikidata.org/entity/Q254>"]
+            },
+            "target": { "id": "?death", "label": "date of death", "prefix": { "iri": "", "abbreviation": "" } },
+            "properties": [
+                { "id": "P570", "label": "date of death", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }
+    ]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(
+        query.contains("WHERE {\n    VALUES ?item { wd:Q5879 wd:Q1339 <http://www.wikidata.org/entity/Q254> }\n"),
+        "{}",
+        query
+    );
+    assert_eq!(query.matches("VALUES").count(), 1, "{}", query);
+    assert!(query.contains("PREFIX wd: <http://www.wikidata.org/entity/>"));
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_values_block() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?item ?birth WHERE {
+    VALUES ?item { wd:Q5879 wd:Q1339 "literal" 42 }
+    ?item wdt:P569 ?birth .
+}"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 1);
+    assert_eq!(
+        connections[0]["source"]["values"],
+        json!(["Q5879", "Q1339", "\"literal\"", "42"])
+    );
+    assert_eq!(connections[0]["source"]["prefix"]["abbreviation"], "wd");
+    assert_eq!(connections[0]["target"]["values"], json!([]));
+
+    // literals are not prefixed
+    let regenerated = vqg_to_query_wasm(&Value::Array(connections).to_string(), false, false);
+    assert!(
+        regenerated.contains("VALUES ?item { wd:Q5879 wd:Q1339 \"literal\" 42 }\n"),
+        "{}",
+        regenerated
+    );
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_import_values_with_undef() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?birth ?item WHERE {
+    VALUES ?item { wd:Q5879 UNDEF }
+    ?item wdt:P569 ?birth .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"][0]["source"]["values"], json!(["Q5879", "UNDEF"]));
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_import_refuses_values_which_cannot_be_kept() {
+    let queries = [
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?item ?year WHERE {
+    VALUES (?item ?year) { (wd:Q5879 1749) }
+    ?item wdt:P569 ?birth .
+}"###,
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?item WHERE {
+    VALUES ?year { 1749 }
+    ?item wdt:P569 ?birth .
+    FILTER(YEAR(?birth) = ?year)
+}"###,
+    ];
+
+    for query in queries {
+        let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+        assert_eq!(graph["connections"], json!([]), "{}", query);
+    }
+}
+
+#[test]
+fn test_import_trailing_values_block() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?item ?birth WHERE {
+    ?item wdt:P569 ?birth .
+}
+VALUES ?item { wd:Q5879 }"###;
+
+    let connections = parse_connections_json(&query_to_vqg_wasm(query));
+
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0]["source"]["values"], json!(["Q5879"]));
+    assert_eq!(connections[0]["source"]["prefix"]["abbreviation"], "wd");
+}
+
+#[test]
+fn test_values_round_trip() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?birth ?item WHERE {
+    VALUES ?item { wd:Q5879 wd:Q1339 }
+    ?item wdt:P569 ?birth .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_values_stay_in_their_block() {
+    let queries = [
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?a ?h WHERE {
+    ?h wdt:P31 wd:Q5 .
+    OPTIONAL {
+        VALUES ?a { wd:Q64 wd:Q90 }
+        ?h wdt:P19 ?a .
+    }
+}"###,
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?a ?h WHERE {
+    { VALUES ?a { wd:Q64 } ?h wdt:P19 ?a . } UNION { ?h wdt:P20 ?a . }
+}"###,
+    ];
+
+    for query in queries {
+        let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+        assert_eq!(
+            Query::parse(&regenerated, None).unwrap().to_string(),
+            Query::parse(query, None).unwrap().to_string(),
+            "{}",
+            regenerated
+        );
+    }
+}
+
+#[test]
+fn test_import_refuses_values_of_a_variable_only_bound_in_a_block() {
+    // the VALUES restrict the whole query, not only the OPTIONAL block
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?a ?h WHERE {
+    VALUES ?a { wd:Q64 }
+    ?h wdt:P31 wd:Q5 .
+    OPTIONAL { ?h wdt:P19 ?a . }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"], json!([]));
+}
+
+fn painters_subquery() -> Value {
+    json!({
+        "label": "ten painters",
+        "graph": {
+            "connections": [{
+                "source": { "id": "?artist", "label": "artist", "prefix": { "iri": "", "abbreviation": "" } },
+                "target": { "id": "Q1028181", "label": "painter", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" }, "selectedForProjection": false },
+                "properties": [
+                    { "id": "P106", "label": "occupation", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+                ]
+            }],
+            "settings": { "limit": 10 }
+        }
+    })
+}
+
+fn paintings_of_painters() -> Value {
+    json!({
+        "connections": [{
+            "source": { "id": "?painting", "label": "painting", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?artist", "label": "artist", "prefix": { "iri": "", "abbreviation": "" }, "selectedForProjection": false },
+            "properties": [
+                { "id": "P170", "label": "creator", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }],
+        "subqueries": [painters_subquery()]
+    })
+}
+
+#[test]
+fn test_subquery_is_rendered_inline() {
+    let query = vqg_to_query_wasm(&paintings_of_painters().to_string(), false, false);
+
+    let expected = r###"SELECT ?artist ?painting WHERE {
+    {
+        SELECT ?artist WHERE {
+            ?artist wdt:P106 wd:Q1028181 .
+            # artist -- [occupation] -> painter
+        }
+        LIMIT 10
+    }
+    ?painting wdt:P170 ?artist .
+    # painting -- [creator] -> artist
+}"###;
+    assert!(query.ends_with(expected), "{}", query);
+    assert!(query.starts_with("PREFIX wd: <http://www.wikidata.org/entity/>\nPREFIX wdt:"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_subquery_variables_are_labelled_by_the_outer_service() {
+    let query = vqg_to_query_wasm(&paintings_of_painters().to_string(), true, true);
+
+    assert!(query.contains("SELECT ?artist ?artistLabel ?painting ?paintingLabel WHERE"), "{}", query);
+    assert!(query.contains("        SELECT ?artist WHERE"), "{}", query);
+    assert_eq!(query.matches("SERVICE wikibase:label").count(), 1, "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_subquery() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?painting ?artist WHERE {
+    {
+        SELECT DISTINCT ?artist WHERE {
+            ?artist wdt:P106 wd:Q1028181 .
+        }
+        ORDER BY ?artist
+        LIMIT 10
+    }
+    ?painting wdt:P170 ?artist .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 1);
+    let subquery = &graph["subqueries"][0]["graph"];
+    assert_eq!(subquery["connections"][0]["source"]["id"], "?artist");
+    assert_eq!(subquery["connections"][0]["source"]["distinct"], Value::Bool(true));
+    assert_eq!(subquery["settings"]["limit"], 10);
+    assert_eq!(subquery["settings"]["orderBy"][0]["variable"], "?artist");
+}
+
+#[test]
+fn test_import_refuses_subquery_inside_optional_or_union() {
+    for block in [
+        "OPTIONAL { { SELECT ?h ?x WHERE { ?h wdt:P570 ?x . } LIMIT 1 } }",
+        "{ ?h wdt:P19 ?x . } UNION { { SELECT ?h ?x WHERE { ?h wdt:P20 ?x . } LIMIT 1 } }",
+    ] {
+        let query = format!(
+            "PREFIX wdt: <http://www.wikidata.org/prop/direct/>\nSELECT ?h ?x WHERE {{\n    ?h wdt:P31 ?c .\n    {}\n}}",
+            block
+        );
+
+        let graph = parse_graph_json(&query_to_graph_wasm(&query));
+
+        assert_eq!(graph["connections"], json!([]), "{}", query);
+        assert_eq!(graph["subqueries"], json!([]), "{}", query);
+    }
+}
+
+#[test]
+fn test_subquery_round_trip() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?artist ?count WHERE {
+    {
+        SELECT ?artist (COUNT(?painting) AS ?count) WHERE {
+            ?painting wdt:P170 ?artist .
+        }
+        GROUP BY ?artist
+        ORDER BY DESC(?count)
+        LIMIT 1
+    }
+    ?artist wdt:P106 wd:Q1028181 .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    let normalize = |q: &str| {
+        // spargebra names the internal aggregate variables randomly
+        let parsed = Query::parse(q, None).unwrap().to_string();
+        parsed
+            .split_whitespace()
+            .map(|token| if token.len() > 30 && token.contains('?') { "?_" } else { token })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(normalize(&regenerated), normalize(query), "{}", regenerated);
+}
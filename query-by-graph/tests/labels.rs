@@ -0,0 +1,287 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, vqg_to_query_wasm};
+use serde_json::{json, Value};
+use spargebra::Query;
+use common::{assert_parses, parse_graph_json, educated_at};
+
+/// `?person wdt:P69 ?university` with the given settings.
+fn educated_at_with_settings(settings: Value) -> Value {
+    json!({ "connections": educated_at(), "settings": settings })
+}
+
+#[test]
+fn test_label_service_uses_configured_languages() {
+    let graph = educated_at_with_settings(json!({ "labels": { "languages": ["de", "mul", "en"] } }));
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, true);
+
+    let expected = r###"PREFIX bd: <http://www.bigdata.com/rdf#>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+
+SELECT ?person ?personLabel ?university ?universityLabel WHERE {
+    ?person wdt:P69 ?university .
+    # person -- [educated at] -> university
+    SERVICE wikibase:label { bd:serviceParam wikibase:language "de,mul,en". }
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_label_service_defaults_to_auto_language() {
+    let with_settings = vqg_to_query_wasm(&educated_at_with_settings(json!({ "labels": {} })).to_string(), false, false);
+    let with_flag = vqg_to_query_wasm(&educated_at_with_settings(json!({})).to_string(), true, false);
+
+    let service = "SERVICE wikibase:label { bd:serviceParam wikibase:language \"[AUTO_LANGUAGE],en\". }";
+    assert!(with_settings.contains(service), "{}", with_settings);
+    assert_eq!(with_settings, with_flag);
+}
+
+#[test]
+fn test_import_reads_label_service_languages() {
+    let query = r###"PREFIX bd: <http://www.bigdata.com/rdf#>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?person ?personLabel WHERE {
+    ?person wdt:P69 ?university .
+    SERVICE wikibase:label { bd:serviceParam wikibase:language "de, en". }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["settings"]["labels"]["languages"], json!(["de", "en"]));
+}
+
+#[test]
+fn test_import_without_label_service_has_no_label_settings() {
+    let auto = r###"PREFIX bd: <http://www.bigdata.com/rdf#>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?person WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    SERVICE wikibase:label { bd:serviceParam wikibase:language "[AUTO_LANGUAGE],en". }
+}"###;
+    let without = "SELECT ?person WHERE { ?person <http://www.wikidata.org/prop/direct/P69> ?university . }";
+
+    let auto_graph = parse_graph_json(&query_to_graph_wasm(auto));
+    let without_graph = parse_graph_json(&query_to_graph_wasm(without));
+
+    assert_eq!(auto_graph["settings"]["labels"], json!({ "languages": [], "strategy": "service" }));
+    assert_eq!(without_graph["settings"]["labels"], Value::Null);
+}
+
+#[test]
+fn test_label_languages_round_trip() {
+    let query = r###"PREFIX bd: <http://www.bigdata.com/rdf#>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?person ?personLabel ?university ?universityLabel WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    SERVICE wikibase:label { bd:serviceParam wikibase:language "de,en". }
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, true);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_rdfs_label_strategy_renders_optional_blocks_per_language() {
+    let graph = educated_at_with_settings(json!({ "labels": { "languages": ["de", "en"], "strategy": "rdfsLabel" } }));
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, true);
+
+    let expected = r###"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+
+SELECT ?person ?personLabel ?university ?universityLabel WHERE {
+    ?person wdt:P69 ?university .
+    # person -- [educated at] -> university
+    OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "de") }
+    OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "en") }
+    OPTIONAL { ?university rdfs:label ?universityLabel . FILTER(LANG(?universityLabel) = "de") }
+    OPTIONAL { ?university rdfs:label ?universityLabel . FILTER(LANG(?universityLabel) = "en") }
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_rdfs_label_strategy_skips_auto_language() {
+    let graph = educated_at_with_settings(json!({ "labels": { "languages": ["[AUTO_LANGUAGE]"], "strategy": "rdfsLabel" } }));
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("FILTER(LANG(?personLabel) = \"en\")"), "{}", query);
+    assert!(!query.contains("AUTO_LANGUAGE"), "{}", query);
+    assert!(!query.contains("SERVICE"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_maps_rdfs_label_blocks_to_label_settings() {
+    let query = r###"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+SELECT ?person ?personLabel WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "de") }
+    OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "en") }
+    OPTIONAL { ?university rdfs:label ?universityLabel . FILTER(LANG(?universityLabel) = "de") }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    // the label of the university is not projected, so its block is no label block
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 2, "{}", graph);
+    assert_eq!(connections[1]["target"]["id"], "?universityLabel");
+    assert_eq!(connections[1]["optional"], true);
+    assert_eq!(
+        graph["settings"]["labels"],
+        json!({ "languages": ["de", "en"], "strategy": "rdfsLabel" })
+    );
+}
+
+#[test]
+fn test_rdfs_label_block_of_a_filtered_label_round_trip() {
+    let query = r###"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x WHERE {
+    ?x wdt:P31 ?class .
+    OPTIONAL { ?x rdfs:label ?xLabel }
+    FILTER(CONTAINS(?xLabel, "a"))
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_rdfs_label_round_trip() {
+    let query = r###"PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+SELECT ?person ?personLabel ?university ?universityLabel WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = "fr") }
+    OPTIONAL { ?university rdfs:label ?universityLabel . FILTER(LANG(?universityLabel) = "fr") }
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_companions_add_description_and_alias_columns() {
+    let mut graph = educated_at_with_settings(json!({}));
+    graph["connections"][0]["source"]["companions"] = json!({ "description": true, "altLabel": true });
+    graph["connections"][0]["target"]["companions"] = json!({ "label": false });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, true);
+
+    assert!(
+        query.contains("SELECT ?person ?personAltLabel ?personDescription ?personLabel ?university WHERE {"),
+        "{}",
+        query
+    );
+    assert!(query.contains("SERVICE wikibase:label"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_companions_with_rdfs_label_strategy() {
+    let mut graph = educated_at_with_settings(json!({ "labels": { "languages": ["de"], "strategy": "rdfsLabel" } }));
+    graph["connections"][0]["source"]["companions"] = json!({ "label": false, "description": true, "altLabel": true });
+    graph["connections"][0]["target"]["selectedForProjection"] = json!(false);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    let expected = r###"PREFIX schema: <http://schema.org/>
+PREFIX skos: <http://www.w3.org/2004/02/skos/core#>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+
+SELECT ?person ?personAltLabel ?personDescription WHERE {
+    ?person wdt:P69 ?university .
+    # person -- [educated at] -> university
+    OPTIONAL { ?person schema:description ?personDescription . FILTER(LANG(?personDescription) = "de") }
+    OPTIONAL { ?person skos:altLabel ?personAltLabel . FILTER(LANG(?personAltLabel) = "de") }
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_alt_labels_with_rdfs_label_strategy_are_not_aggregated() {
+    let mut graph = educated_at_with_settings(json!({ "labels": { "languages": ["de", "en"], "strategy": "rdfsLabel" } }));
+    graph["connections"][0]["source"]["companions"] = json!({ "altLabel": true });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    // every alias is a row of its own, unlike the comma separated list of
+    // the label service
+    assert!(
+        query.contains(
+            "    OPTIONAL { ?person skos:altLabel ?personAltLabel . FILTER(LANG(?personAltLabel) = \"de\") }\n    \
+             OPTIONAL { ?person skos:altLabel ?personAltLabel . FILTER(LANG(?personAltLabel) = \"en\") }\n"
+        ),
+        "{}",
+        query
+    );
+    assert!(!query.contains("GROUP_CONCAT"), "{}", query);
+    assert!(!query.contains("GROUP BY"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_reads_companions_from_projection() {
+    let query = r###"PREFIX bd: <http://www.bigdata.com/rdf#>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?person ?personDescription ?university ?universityLabel ?universityAltLabel WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    SERVICE wikibase:label { bd:serviceParam wikibase:language "en". }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(
+        connection["source"]["companions"],
+        json!({ "label": false, "description": true, "altLabel": false })
+    );
+    assert_eq!(
+        connection["target"]["companions"],
+        json!({ "label": true, "description": false, "altLabel": true })
+    );
+}
+
+#[test]
+fn test_companions_round_trip_with_rdfs_label_strategy() {
+    let query = r###"PREFIX schema: <http://schema.org/>
+SELECT ?person ?personDescription WHERE {
+    ?person <http://www.wikidata.org/prop/direct/P69> ?university .
+    OPTIONAL { ?person schema:description ?personDescription . FILTER(LANG(?personDescription) = "en") }
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
@@ -96,14 +96,10 @@ SELECT ?3 ?3Label WHERE {
     let c = &connections[0];
 
     assert_eq!(c["properties"][0]["id"], Value::String("?3".to_string()));
-    assert_eq!(
-        c["source"]["id"],
-        Value::String("<http://www.wikidata.org/entity/Q5879>".to_string())
-    );
-    assert_eq!(
-        c["target"]["id"],
-        Value::String("<http://www.wikidata.org/entity/Q2079>".to_string())
-    );
+    assert_eq!(c["source"]["id"], Value::String("Q5879".to_string()));
+    assert_eq!(c["target"]["id"], Value::String("Q2079".to_string()));
+    assert_eq!(c["source"]["prefix"]["iri"], "http://www.wikidata.org/entity/");
+    assert_eq!(c["source"]["prefix"]["abbreviation"], "wd");
 
     // selected_for_projection exists and defaults/sets correctly in current model
     assert_eq!(c["properties"][0]["selectedForProjection"], Value::Bool(true));
@@ -219,9 +215,11 @@ fn test_query_to_vqg_empty_input() {
 }
 
 #[test]
-fn test_non_select_query_returns_empty_vqg() {
+fn test_ask_query_is_imported() {
     let result = query_to_vqg_wasm("ASK WHERE { ?s ?p ?o . }");
-    assert_eq!(result, "[]");
+    let connections = parse_connections_json(&result);
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0]["source"]["id"], Value::String("?s".to_string()));
 }
 
 #[test]
@@ -326,16 +324,16 @@ SELECT ?o ?s WHERE {
 }
 
 #[test]
-fn test_distinct_variable_generates_per_variable_distinct() {
+fn test_distinct_variable_generates_select_distinct() {
     let graph = r###"[{"properties":[{"id":"P69","label":"educated at","prefix":{"iri":"http://www.wikidata.org/prop/direct/","abbreviation":"wdt"},"selectedForProjection":false}],"source":{"id":"Q5879","label":"Johann Wolfgang von Goethe","prefix":{"iri":"http://www.wikidata.org/entity/","abbreviation":"wd"},"selectedForProjection":false,"distinct":false},"target":{"id":"?university","label":"Variable","prefix":{"iri":"","abbreviation":""},"selectedForProjection":true,"distinct":true}}]"###;
 
     let result = vqg_to_query_wasm(graph, false, false);
     let select = select_line(&result);
 
-    // Per-variable DISTINCT: SELECT DISTINCT(?university) WHERE { ... }
-    assert!(select.contains("DISTINCT(?university)"), "Expected DISTINCT(?university) in: {}", select);
-    // SELECT keyword itself should not be followed by the global DISTINCT keyword
-    assert!(!select.starts_with("SELECT DISTINCT "), "Should not use global SELECT DISTINCT, got: {}", select);
+    // SPARQL has no per-variable DISTINCT: SELECT DISTINCT ?university WHERE { ... }
+    assert!(select.starts_with("SELECT DISTINCT ?university WHERE"), "Expected SELECT DISTINCT ?university in: {}", select);
+    assert!(!select.contains("DISTINCT("), "Invalid per-variable DISTINCT(...) in: {}", select);
+    assert!(Query::parse(&result, None).is_ok(), "Generated query should parse:\n{}", result);
 }
 
 #[test]
@@ -350,9 +348,8 @@ fn test_no_distinct_generates_plain_select() {
 }
 
 #[test]
-fn test_parse_select_distinct_query_does_not_set_per_variable_distinct_flag() {
-    // Importing SELECT DISTINCT ?university does NOT set distinct=true on individual variables.
-    // Per-variable distinct is only set via the UI checkbox; the global DISTINCT keyword is ignored on import.
+fn test_parse_select_distinct_query_sets_distinct_flag() {
+    // Importing SELECT DISTINCT ?university sets distinct=true on every projected entity variable.
     let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
 PREFIX wdt: <http://www.wikidata.org/prop/direct/>
 SELECT DISTINCT ?university WHERE {
@@ -367,7 +364,20 @@ SELECT DISTINCT ?university WHERE {
 
     assert_eq!(c["target"]["id"], Value::String("?university".to_string()));
     assert_eq!(c["target"]["selectedForProjection"], Value::Bool(true));
-    assert_eq!(c["target"]["distinct"], Value::Bool(false));
+    assert_eq!(c["target"]["distinct"], Value::Bool(true));
+    assert_eq!(c["source"]["distinct"], Value::Bool(false));
+}
+
+#[test]
+fn test_distinct_round_trip() {
+    let graph = r###"[{"properties":[{"id":"P69","label":"educated at","prefix":{"iri":"http://www.wikidata.org/prop/direct/","abbreviation":"wdt"},"selectedForProjection":false}],"source":{"id":"?person","label":"Variable","prefix":{"iri":"","abbreviation":""},"selectedForProjection":false,"distinct":false},"target":{"id":"?university","label":"Variable","prefix":{"iri":"","abbreviation":""},"selectedForProjection":true,"distinct":true}}]"###;
+
+    let query = vqg_to_query_wasm(graph, false, false);
+    let connections = parse_connections_json(&query_to_vqg_wasm(&query));
+
+    assert_eq!(connections[0]["target"]["distinct"], Value::Bool(true));
+    assert_eq!(connections[0]["source"]["distinct"], Value::Bool(false));
+    assert_sparql_equivalent(&vqg_to_query_wasm(&Value::Array(connections).to_string(), false, false), &query);
 }
 
 #[test]
@@ -400,16 +410,14 @@ fn test_distinct_only_applies_when_variable_is_selected_for_projection() {
 
 #[test]
 fn test_mixed_distinct_and_non_distinct_variables() {
-    // ?university is distinct, ?person is not → only ?university gets DISTINCT(...)
+    // ?university is distinct, ?person is not → the whole result is distinct
     let graph = r###"[{"properties":[{"id":"P69","label":"educated at","prefix":{"iri":"http://www.wikidata.org/prop/direct/","abbreviation":"wdt"},"selectedForProjection":false}],"source":{"id":"?person","label":"Variable","prefix":{"iri":"","abbreviation":""},"selectedForProjection":true,"distinct":false},"target":{"id":"?university","label":"Variable","prefix":{"iri":"","abbreviation":""},"selectedForProjection":true,"distinct":true}}]"###;
 
     let result = vqg_to_query_wasm(graph, false, false);
     let select = select_line(&result);
 
-    assert!(select.contains("DISTINCT(?university)"), "Expected DISTINCT(?university) in: {}", select);
-    // ?person should appear as a plain variable (no DISTINCT wrapper)
-    assert!(select.contains("?person"), "Expected ?person in: {}", select);
-    assert!(!select.contains("DISTINCT(?person)"), "?person should not be wrapped in DISTINCT: {}", select);
+    assert!(select.starts_with("SELECT DISTINCT ?person ?university WHERE"), "Expected SELECT DISTINCT in: {}", select);
+    assert!(Query::parse(&result, None).is_ok(), "Generated query should parse:\n{}", result);
 }
 
 #[test]
@@ -420,7 +428,7 @@ fn test_label_vars_not_distinct_even_when_base_var_is_distinct() {
     let result = vqg_to_query_wasm(graph, true, true);
     let select = select_line(&result);
 
-    assert!(select.contains("DISTINCT(?university)"), "Expected DISTINCT(?university) in: {}", select);
-    assert!(!select.contains("DISTINCT(?universityLabel)"), "Label variable must not be wrapped in DISTINCT: {}", select);
+    assert!(select.starts_with("SELECT DISTINCT ?university ?universityLabel WHERE"), "Expected SELECT DISTINCT in: {}", select);
+    assert!(!select.contains("DISTINCT("), "Label variable must not be wrapped in DISTINCT: {}", select);
     assert!(select.contains("?universityLabel"), "Label variable should still appear: {}", select);
 }
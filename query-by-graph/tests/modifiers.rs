@@ -0,0 +1,545 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, vqg_to_query_wasm};
+use serde_json::{json, Value};
+use spargebra::Query;
+use common::{assert_parses, parse_graph_json, educated_at};
+
+#[test]
+fn test_limit_and_offset_generation() {
+    let graph = json!({
+        "connections": educated_at(),
+        "settings": { "limit": 100, "offset": 20 }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.ends_with("}\nLIMIT 100\nOFFSET 20"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_order_by_generation() {
+    let graph = json!({
+        "connections": educated_at(),
+        "settings": {
+            "orderBy": [
+                { "variable": "?university", "descending": true },
+                { "variable": "person" }
+            ],
+            "limit": 10
+        }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, true);
+
+    assert!(query.ends_with("}\nORDER BY DESC(?university) ?person\nLIMIT 10"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_no_settings_generate_no_modifiers() {
+    let query = vqg_to_query_wasm(&educated_at().to_string(), false, false);
+
+    assert!(query.ends_with('}'), "{}", query);
+}
+
+#[test]
+fn test_import_solution_modifiers() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT DISTINCT ?person ?university WHERE {
+    ?person wdt:P69 ?university .
+}
+ORDER BY ?person DESC(?university)
+LIMIT 5
+OFFSET 10"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        graph["settings"]["orderBy"],
+        json!([
+            { "variable": "?person", "descending": false },
+            { "variable": "?university", "descending": true }
+        ])
+    );
+    assert_eq!(graph["settings"]["limit"], 5);
+    assert_eq!(graph["settings"]["offset"], 10);
+}
+
+#[test]
+fn test_import_limit_without_projection() {
+    let query = "SELECT * WHERE { ?s ?p ?o . } LIMIT 3";
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 1);
+    assert_eq!(graph["settings"]["limit"], 3);
+    assert_eq!(graph["settings"]["offset"], Value::Null);
+}
+
+#[test]
+fn test_import_refuses_expression_order_keys() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x ?s WHERE {
+    ?x wdt:P1449 ?s .
+}
+ORDER BY LCASE(?s) ?x"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"], json!([]));
+    assert_eq!(graph["settings"]["orderBy"], json!([]));
+}
+
+#[test]
+fn test_solution_modifiers_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?person ?university WHERE {
+    ?person wdt:P69 ?university .
+}
+ORDER BY DESC(?person)
+LIMIT 50
+OFFSET 100"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
+
+fn students_per_university(having: Value) -> Value {
+    json!({
+        "connections": [
+            {
+                "source": {
+                    "id": "?student", "label": "student", "prefix": { "iri": "", "abbreviation": "" },
+                    "aggregates": [{ "function": "count", "distinct": true, "alias": "?students" }]
+                },
+                "target": { "id": "?university", "label": "university", "prefix": { "iri": "", "abbreviation": "" } },
+                "properties": [
+                    { "id": "P69", "label": "educated at", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+                ]
+            }
+        ],
+        "settings": { "having": having }
+    })
+}
+
+#[test]
+fn test_aggregate_projection_with_inferred_group_by() {
+    let query = vqg_to_query_wasm(&students_per_university(json!([])).to_string(), false, false);
+
+    assert!(
+        query.contains("SELECT ?university (COUNT(DISTINCT ?student) AS ?students) WHERE {"),
+        "{}",
+        query
+    );
+    assert!(query.ends_with("}\nGROUP BY ?university"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_aggregate_with_label_service_groups_by_labels() {
+    let query = vqg_to_query_wasm(&students_per_university(json!([])).to_string(), true, true);
+
+    assert!(query.contains("GROUP BY ?university ?universityLabel"), "{}", query);
+    assert!(!query.contains("?studentLabel"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_having_condition_generation() {
+    let having = json!([
+        {
+            "type": "comparison",
+            "operator": ">",
+            "left": { "type": "aggregate", "function": "count", "distinct": true, "argument": { "type": "variable", "name": "?student" } },
+            "right": { "type": "term", "value": "1000" }
+        }
+    ]);
+
+    let query = vqg_to_query_wasm(&students_per_university(having).to_string(), false, false);
+
+    assert!(
+        query.ends_with("GROUP BY ?university\nHAVING (COUNT(DISTINCT ?student) > 1000)"),
+        "{}",
+        query
+    );
+    assert_parses(&query);
+}
+
+#[test]
+fn test_all_aggregate_functions() {
+    let graph = json!([
+        {
+            "source": {
+                "id": "?person", "label": "person", "prefix": { "iri": "", "abbreviation": "" },
+                "selectedForProjection": false
+            },
+            "target": {
+                "id": "?birth", "label": "birth", "prefix": { "iri": "", "abbreviation": "" },
+                "aggregates": [
+                    { "function": "sum", "alias": "?sum" },
+                    { "function": "avg", "alias": "?avg" },
+                    { "function": "min", "alias": "?min" },
+                    { "function": "max", "alias": "?max" },
+                    { "function": "sample", "alias": "?sample" },
+                    { "function": "groupConcat", "distinct": true, "alias": "?all", "separator": ", " }
+                ]
+            },
+            "properties": [
+                { "id": "P569", "label": "date of birth", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }
+    ]);
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains(
+        "SELECT (SUM(?birth) AS ?sum) (AVG(?birth) AS ?avg) (MIN(?birth) AS ?min) (MAX(?birth) AS ?max) (SAMPLE(?birth) AS ?sample) (GROUP_CONCAT(DISTINCT ?birth; SEPARATOR=\", \") AS ?all) WHERE {"
+    ), "{}", query);
+    assert!(!query.contains("GROUP BY"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_aggregates_and_having() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?university (COUNT(DISTINCT ?student) AS ?students) (GROUP_CONCAT(?student; SEPARATOR="|") AS ?names) WHERE {
+    ?student wdt:P69 ?university .
+}
+GROUP BY ?university
+HAVING (COUNT(?student) > 10)
+ORDER BY DESC(?students)
+LIMIT 10"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(
+        connection["source"]["aggregates"],
+        json!([
+            { "function": "count", "distinct": true, "alias": "?students", "separator": null },
+            { "function": "groupConcat", "distinct": false, "alias": "?names", "separator": "|" }
+        ])
+    );
+    assert_eq!(connection["source"]["selectedForProjection"], Value::Bool(false));
+    assert_eq!(connection["target"]["selectedForProjection"], Value::Bool(true));
+    assert_eq!(
+        graph["settings"]["having"],
+        json!([
+            {
+                "type": "comparison",
+                "operator": ">",
+                "left": { "type": "aggregate", "function": "count", "distinct": false, "argument": { "type": "variable", "name": "?student" }, "separator": null },
+                "right": { "type": "term", "value": "10" }
+            }
+        ])
+    );
+    assert_eq!(graph["settings"]["orderBy"][0]["variable"], "?students");
+    assert_eq!(graph["settings"]["limit"], 10);
+}
+
+#[test]
+fn test_import_orders_by_alias_of_aggregate() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?university (COUNT(?student) AS ?students) WHERE {
+    ?student wdt:P69 ?university .
+}
+GROUP BY ?university
+ORDER BY DESC(COUNT(?student))"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+    assert_eq!(graph["settings"]["orderBy"], json!([{ "variable": "?students", "descending": true }]));
+
+    // an aggregate which is not projected has no alias to order by
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?university WHERE {
+    ?student wdt:P69 ?university .
+}
+GROUP BY ?university
+ORDER BY DESC(COUNT(?student))"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+    assert_eq!(graph["connections"], json!([]));
+}
+
+#[test]
+fn test_aggregation_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?university (COUNT(DISTINCT ?student) AS ?students) WHERE {
+    ?student wdt:P69 ?university .
+}
+GROUP BY ?university
+HAVING (COUNT(DISTINCT ?student) > 10)
+ORDER BY DESC(?students)"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    let normalize = |q: &str| {
+        // spargebra names the internal aggregate variables randomly
+        let parsed = Query::parse(q, None).unwrap().to_string();
+        parsed
+            .split_whitespace()
+            .map(|token| if token.len() > 30 && token.contains('?') { "?_" } else { token })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(normalize(&regenerated), normalize(query), "{}", regenerated);
+}
+
+#[test]
+fn test_import_keeps_explicit_group_keys() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT (COUNT(?x) AS ?c) WHERE {
+    ?x wdt:P31 ?class .
+}
+GROUP BY ?class"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+    assert_eq!(graph["settings"]["groupBy"], json!(["?class"]));
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.ends_with("\nGROUP BY ?class"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_explicit_group_keys_generation() {
+    let graph = json!({
+        "connections": educated_at(),
+        "settings": { "groupBy": ["?university", "?person"] }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.ends_with("\nGROUP BY ?university ?person"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_explicit_group_keys_are_completed_by_projected_variables() {
+    let mut connections = educated_at();
+    connections[0]["source"]["aggregates"] = json!([
+        { "function": "count", "distinct": false, "alias": "?count", "separator": null }
+    ]);
+    connections[0]["target"]["selectedForProjection"] = json!(true);
+    let graph = json!({
+        "connections": connections,
+        "settings": { "groupBy": ["?person"] }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.ends_with("\nGROUP BY ?person ?university"), "{}", query);
+    assert_parses(&query);
+}
+
+fn date_of_birth() -> Value {
+    json!([
+        {
+            "source": { "id": "?person", "label": "person", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?birth", "label": "birth", "prefix": { "iri": "", "abbreviation": "" }, "selectedForProjection": false },
+            "properties": [
+                { "id": "P569", "label": "date of birth", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }
+    ])
+}
+
+fn birth_year() -> Value {
+    json!({
+        "type": "function",
+        "name": "YEAR",
+        "arguments": [{ "type": "variable", "name": "?birth" }]
+    })
+}
+
+#[test]
+fn test_bind_generation() {
+    let graph = json!({
+        "connections": date_of_birth(),
+        "computed": [{ "alias": "?birthYear", "expression": birth_year(), "bind": true }],
+        "filters": [
+            { "type": "comparison", "operator": "<", "left": { "type": "variable", "name": "?birthYear" }, "right": { "type": "term", "value": "1800" } }
+        ]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("SELECT ?birthYear ?person WHERE"), "{}", query);
+    assert!(query.contains("    BIND(YEAR(?birth) AS ?birthYear)\n"), "{}", query);
+    assert!(query.contains("FILTER(?birthYear < 1800)"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_projection_expression_generation() {
+    let graph = json!({
+        "connections": date_of_birth(),
+        "computed": [
+            {
+                "alias": "?id",
+                "expression": { "type": "function", "name": "STR", "arguments": [{ "type": "variable", "name": "?person" }] }
+            },
+            { "alias": "?hidden", "expression": birth_year(), "selectedForProjection": false }
+        ]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("SELECT ?person (STR(?person) AS ?id) WHERE"), "{}", query);
+    assert!(!query.contains("BIND"), "{}", query);
+    assert!(!query.contains("?hidden"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_arithmetic_expression_generation() {
+    let graph = json!({
+        "connections": date_of_birth(),
+        "computed": [{
+            "alias": "?age",
+            "expression": {
+                "type": "arithmetic",
+                "operator": "-",
+                "left": { "type": "term", "value": "2000" },
+                "right": birth_year()
+            },
+            "bind": true
+        }]
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("BIND(2000 - YEAR(?birth) AS ?age)"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_bind_and_projection_expressions() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?person (STR(?person) AS ?id) ?birthYear WHERE {
+    ?person wdt:P569 ?birth .
+    BIND(YEAR(?birth) AS ?birthYear)
+    ?person wdt:P19 ?place .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(
+        graph["computed"],
+        json!([
+            { "alias": "?id", "expression": { "type": "function", "name": "STR", "arguments": [{ "type": "variable", "name": "?person" }] }, "bind": false, "selectedForProjection": true },
+            { "alias": "?birthYear", "expression": birth_year(), "bind": true, "selectedForProjection": true }
+        ])
+    );
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_bind_round_trip() {
+    let queries = [
+        // a constant is bound before the triples which use it
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x WHERE {
+    BIND(wd:Q5 AS ?class)
+    ?x wdt:P31 ?class .
+}"###,
+        r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x ?year WHERE {
+    ?x wdt:P31 wd:Q5 .
+    OPTIONAL {
+        ?x wdt:P569 ?birth .
+        BIND(YEAR(?birth) AS ?year)
+    }
+}"###,
+        r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?date ?x WHERE {
+    {
+        ?x wdt:P569 ?date .
+    }
+    UNION
+    {
+        ?x wdt:P570 ?death .
+        BIND(YEAR(?death) AS ?date)
+    }
+}"###,
+    ];
+
+    for query in queries {
+        let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+        assert_eq!(
+            Query::parse(&regenerated, None).unwrap().to_string(),
+            Query::parse(query, None).unwrap().to_string(),
+            "{}",
+            regenerated
+        );
+    }
+}
+
+#[test]
+fn test_import_keeps_bind_in_its_block() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x ?year WHERE {
+    ?x wdt:P31 wd:Q5 .
+    OPTIONAL { ?x wdt:P569 ?birth . BIND(YEAR(?birth) AS ?year) }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["computed"], json!([]));
+    assert_eq!(
+        graph["connections"][1]["computed"],
+        json!([{ "alias": "?year", "expression": birth_year(), "bind": true, "selectedForProjection": true }])
+    );
+}
+
+#[test]
+fn test_import_refuses_bind_used_before_it_is_rendered() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?x ?y WHERE {
+    ?x wdt:P569 ?birth .
+    BIND(YEAR(?birth) AS ?year)
+    ?y wdt:P585 ?year .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"], json!([]));
+    assert_eq!(graph["computed"], json!([]));
+}
+
+#[test]
+fn test_import_unattached_aggregate_as_computed() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?university (COUNT(*) AS ?count) WHERE {
+    ?student wdt:P69 ?university .
+}
+GROUP BY ?university"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(
+        graph["computed"],
+        json!([{
+            "alias": "?count",
+            "expression": { "type": "aggregate", "function": "count", "distinct": false, "argument": null, "separator": null },
+            "bind": false,
+            "selectedForProjection": true
+        }])
+    );
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.contains("SELECT ?university (COUNT(*) AS ?count) WHERE"), "{}", regenerated);
+    assert!(regenerated.ends_with("GROUP BY ?university"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
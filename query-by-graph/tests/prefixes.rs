@@ -0,0 +1,194 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, query_to_graph_with_wikibases_wasm, vqg_to_query_wasm};
+use serde_json::json;
+use common::{assert_parses, parse_graph_json};
+
+#[test]
+fn test_import_compacts_iris_like_the_editor() {
+    let query = "SELECT ?relation WHERE { <http://www.wikidata.org/entity/Q5879> ?relation <http://www.wikidata.org/entity/Q154804> . }";
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let source = &graph["connections"][0]["source"];
+    assert_eq!(source["id"], "Q5879");
+    assert_eq!(source["label"], "wd:Q5879");
+    assert_eq!(
+        source["prefix"],
+        json!({ "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" })
+    );
+    assert_eq!(graph["connections"][0]["target"]["id"], "Q154804");
+    // variables keep their empty prefix
+    assert_eq!(graph["connections"][0]["properties"][0]["prefix"]["iri"], "");
+}
+
+#[test]
+fn test_import_compacts_with_declared_prefixes() {
+    let query = r###"PREFIX foaf: <http://xmlns.com/foaf/0.1/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?person ?name WHERE {
+    ?person wdt:P69 ?university .
+    ?person foaf:name ?name .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections[0]["properties"][0]["id"], "P69");
+    assert_eq!(connections[0]["properties"][0]["prefix"]["abbreviation"], "wdt");
+    assert_eq!(connections[1]["properties"][0]["id"], "name");
+    assert_eq!(
+        connections[1]["properties"][0]["prefix"],
+        json!({ "iri": "http://xmlns.com/foaf/0.1/", "abbreviation": "foaf" })
+    );
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.contains("PREFIX foaf: <http://xmlns.com/foaf/0.1/>\n"), "{}", regenerated);
+    assert!(regenerated.contains("    ?person foaf:name ?name .\n"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_only_the_prologue_declares_prefixes() {
+    let query = r###"# prefix wd: <http://evil.example/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+base <http://example.org/>
+SELECT ?item WHERE {
+    ?item wdt:P31 <http://www.wikidata.org/entity/Q5> .
+    ?item wdt:P1449 "prefix wdt: <http://evil.example/>" .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(
+        connections[0]["target"]["prefix"],
+        json!({ "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" })
+    );
+    assert_eq!(
+        connections[1]["properties"][0]["prefix"],
+        json!({ "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" })
+    );
+}
+
+#[test]
+fn test_import_prefers_the_longest_namespace() {
+    let query = r###"PREFIX wikidata: <http://www.wikidata.org/>
+SELECT ?item WHERE { ?item <http://www.wikidata.org/prop/direct/P31> wikidata:Q5 . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(connection["properties"][0]["id"], "P31");
+    assert_eq!(connection["properties"][0]["prefix"]["abbreviation"], "wdt");
+    assert_eq!(connection["target"]["id"], "Q5");
+    assert_eq!(connection["target"]["prefix"]["abbreviation"], "wikidata");
+}
+
+#[test]
+fn test_declared_abbreviation_shadows_a_known_prefix() {
+    let query = r###"PREFIX wd: <http://example.org/>
+SELECT ?item WHERE { ?item wd:knows <http://www.wikidata.org/entity/Q5> . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(connection["properties"][0]["id"], "knows");
+    assert_eq!(connection["properties"][0]["prefix"]["iri"], "http://example.org/");
+    // `wd:` is taken, so the Wikidata item stays a full IRI
+    assert_eq!(connection["target"]["id"], "<http://www.wikidata.org/entity/Q5>");
+    assert_eq!(connection["target"]["prefix"]["iri"], "");
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(
+        regenerated.contains("    ?item wd:knows <http://www.wikidata.org/entity/Q5> .\n"),
+        "{}",
+        regenerated
+    );
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_iris_without_a_valid_local_name_stay_full() {
+    let query = r###"PREFIX ex: <http://example.org/>
+SELECT ?item WHERE { ?item <http://example.org/path/to> <http://example.org/> . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(connection["properties"][0]["id"], "<http://example.org/path/to>");
+    assert_eq!(connection["target"]["id"], "<http://example.org/>");
+}
+
+#[test]
+fn test_import_compacts_with_custom_wikibases() {
+    let wikibases = json!([{
+        "name": "Example",
+        "base": "https://example.wikibase.cloud/",
+        "prefixes": {
+            "entity": "exd", "direct": "exdt", "claim": "exp", "statement": "exps",
+            "statementValue": "expsv", "qualifier": "expq", "qualifierValue": "expqv",
+            "reference": "expr", "noValue": "exdno", "statementNode": "exds", "referenceNode": "exdref"
+        }
+    }]);
+    let query = "SELECT ?item WHERE { ?item <https://example.wikibase.cloud/prop/direct/P7> <https://example.wikibase.cloud/entity/Q1> . }";
+
+    let graph = parse_graph_json(&query_to_graph_with_wikibases_wasm(query, &wikibases.to_string()));
+
+    let connection = &graph["connections"][0];
+    assert_eq!(connection["properties"][0]["id"], "P7");
+    assert_eq!(connection["properties"][0]["prefix"]["abbreviation"], "exdt");
+    assert_eq!(connection["target"]["id"], "Q1");
+    assert_eq!(connection["target"]["prefix"]["abbreviation"], "exd");
+}
+
+#[test]
+fn test_import_compacts_property_paths_and_templates() {
+    let query = r###"PREFIX ex: <http://example.org/>
+CONSTRUCT { ?class ex:member ?item . }
+WHERE { ?item (<http://www.wikidata.org/prop/direct/P31>|<http://www.wikidata.org/prop/direct/P279>)+ ?class . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let path = &graph["connections"][0]["properties"][0];
+    assert_eq!(path["pathType"], "alternation");
+    assert_eq!(path["properties"][0]["id"], "P31");
+    assert_eq!(path["properties"][1]["id"], "P279");
+    assert_eq!(path["properties"][1]["prefix"]["abbreviation"], "wdt");
+    assert_eq!(graph["template"][0]["properties"][0]["id"], "member");
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.contains("?item (wdt:P31|wdt:P279)+ ?class"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_import_compacts_values() {
+    let query = "SELECT ?item WHERE { VALUES ?item { <http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/entity/Q2> } ?item <http://www.wikidata.org/prop/direct/P31> ?class . }";
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let source = &graph["connections"][0]["source"];
+    assert_eq!(source["id"], "?item");
+    assert_eq!(source["values"], json!(["Q1", "Q2"]));
+    assert_eq!(source["prefix"]["abbreviation"], "wd");
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.contains("PREFIX wd: <http://www.wikidata.org/entity/>\n"), "{}", regenerated);
+    assert!(regenerated.contains("VALUES ?item { wd:Q1 wd:Q2 }\n"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_values_of_several_namespaces_stay_full() {
+    let query = "SELECT ?item WHERE { VALUES ?item { <http://www.wikidata.org/entity/Q1> <http://example.org/a> } ?item <http://www.wikidata.org/prop/direct/P31> ?class . }";
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let source = &graph["connections"][0]["source"];
+    assert_eq!(
+        source["values"],
+        json!(["<http://www.wikidata.org/entity/Q1>", "<http://example.org/a>"])
+    );
+    assert_eq!(source["prefix"]["iri"], "");
+}
@@ -17,12 +17,14 @@ fn test_complex_property_path_decomposition() {
     // First part: ex:p1/ex:p2*
     assert_eq!(sub_props[0]["pathType"], "sequence");
     let seq_props = &sub_props[0]["properties"];
-    assert_eq!(seq_props[0]["id"], "ex:p1");
-    assert_eq!(seq_props[1]["id"], "ex:p2");
+    assert_eq!(seq_props[0]["id"], "p1");
+    assert_eq!(seq_props[0]["prefix"]["abbreviation"], "ex");
+    assert_eq!(seq_props[0]["prefix"]["iri"], "http://example.org/");
+    assert_eq!(seq_props[1]["id"], "p2");
     assert_eq!(seq_props[1]["modifier"], "*");
     
     // Second part: ex:p3?
-    assert_eq!(sub_props[1]["id"], "ex:p3");
+    assert_eq!(sub_props[1]["id"], "p3");
     assert_eq!(sub_props[1]["modifier"], "?");
 }
 
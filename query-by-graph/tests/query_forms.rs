@@ -0,0 +1,243 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, vqg_to_query_wasm};
+use serde_json::{json, Value};
+use spargebra::Query;
+use common::{assert_parses, parse_graph_json, educated_at};
+
+fn humans_without_gender() -> Value {
+    json!([
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "Q5", "label": "Human", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" }, "selectedForProjection": false },
+            "properties": [
+                { "id": "P31", "label": "instance of", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        },
+        {
+            "source": { "id": "?human", "label": "human", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?gender", "label": "gender", "prefix": { "iri": "", "abbreviation": "" } },
+            "negation": "notExists",
+            "properties": [
+                { "id": "P21", "label": "sex or gender", "prefix": { "iri": "http://www.wikidata.org/prop/direct/", "abbreviation": "wdt" } }
+            ]
+        }
+    ])
+}
+
+#[test]
+fn test_ask_generation() {
+    let graph = json!({
+        "connections": humans_without_gender(),
+        "settings": { "form": "ask" }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, true);
+
+    let expected = r###"ASK WHERE {
+    ?human wdt:P31 wd:Q5 .
+    # human -- [instance of] -> Human
+    FILTER NOT EXISTS {
+        ?human wdt:P21 ?gender .
+        # human -- [sex or gender] -> gender
+    }
+}"###;
+    assert!(query.ends_with(expected), "{}", query);
+    assert!(!query.contains("SERVICE"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_select_is_the_default_form() {
+    let graph = json!({ "connections": humans_without_gender() });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("\nSELECT ?"), "{}", query);
+    assert!(!query.contains("ASK"), "{}", query);
+}
+
+#[test]
+fn test_import_ask() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+ASK {
+    ?human wdt:P31 wd:Q5 .
+    FILTER NOT EXISTS { ?human wdt:P21 ?gender . }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["settings"]["form"], "ask");
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[1]["negation"], "notExists");
+}
+
+#[test]
+fn test_ask_round_trip() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+ASK WHERE {
+    ?human wdt:P31 wd:Q5 .
+    FILTER NOT EXISTS { ?human wdt:P21 ?gender . }
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_construct_defaults_to_the_pattern() {
+    let graph = json!({
+        "connections": humans_without_gender(),
+        "settings": { "form": "construct", "limit": 100 }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, false);
+
+    let expected = r###"CONSTRUCT {
+    ?human wdt:P31 wd:Q5 .
+    # human -- [instance of] -> Human
+} WHERE {
+    ?human wdt:P31 wd:Q5 .
+    # human -- [instance of] -> Human
+    FILTER NOT EXISTS {
+        ?human wdt:P21 ?gender .
+        # human -- [sex or gender] -> gender
+    }
+}
+LIMIT 100"###;
+    assert!(query.ends_with(expected), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_construct_with_template() {
+    let graph = json!({
+        "connections": educated_at(),
+        "template": [{
+            "source": { "id": "?university", "label": "university", "prefix": { "iri": "", "abbreviation": "" } },
+            "target": { "id": "?person", "label": "person", "prefix": { "iri": "", "abbreviation": "" } },
+            "properties": [
+                { "id": "alumnus", "label": "alumnus", "prefix": { "iri": "http://example.org/", "abbreviation": "ex" } }
+            ]
+        }],
+        "settings": { "form": "construct" }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("PREFIX ex: <http://example.org/>"), "{}", query);
+    assert!(query.contains("CONSTRUCT {\n    ?university ex:alumnus ?person .\n"), "{}", query);
+    assert!(query.contains("} WHERE {\n    ?person wdt:P69 ?university .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_construct_with_template() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+PREFIX ex: <http://example.org/>
+CONSTRUCT { ?university ex:alumnus ?person . }
+WHERE { ?person wdt:P69 ?university . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["settings"]["form"], "construct");
+    assert_eq!(graph["connections"][0]["source"]["id"], "?person");
+    let template = graph["template"].as_array().unwrap();
+    assert_eq!(template.len(), 1);
+    assert_eq!(template[0]["source"]["id"], "?university");
+    assert_eq!(template[0]["properties"][0]["id"], "alumnus");
+    assert_eq!(template[0]["properties"][0]["prefix"]["abbreviation"], "ex");
+}
+
+#[test]
+fn test_import_construct_where_has_default_template() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+CONSTRUCT WHERE { ?person wdt:P69 ?university . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["settings"]["form"], "construct");
+    assert_eq!(graph["template"], json!([]));
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_describe_generation() {
+    let mut connections = educated_at();
+    connections[0]["source"]["selectedForProjection"] = json!(false);
+    let graph = json!({
+        "connections": connections,
+        "settings": { "form": "describe", "limit": 5 }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, true);
+
+    assert!(query.contains("DESCRIBE ?university WHERE {\n    ?person wdt:P69 ?university .\n"), "{}", query);
+    assert!(!query.contains("SERVICE"), "{}", query);
+    assert!(query.ends_with("}\nLIMIT 5"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_describe_without_selected_variables() {
+    let mut connections = educated_at();
+    connections[0]["source"]["selectedForProjection"] = json!(false);
+    connections[0]["target"]["selectedForProjection"] = json!(false);
+    let graph = json!({ "connections": connections, "settings": { "form": "describe" } });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("DESCRIBE * WHERE {"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_describe() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+DESCRIBE ?university WHERE { ?person wdt:P69 ?university . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["settings"]["form"], "describe");
+    let connection = &graph["connections"][0];
+    assert_eq!(connection["source"]["selectedForProjection"], Value::Bool(false));
+    assert_eq!(connection["target"]["selectedForProjection"], Value::Bool(true));
+    assert_eq!(graph["computed"], json!([]));
+}
+
+#[test]
+fn test_import_refuses_described_iris() {
+    let query = r###"PREFIX wd: <http://www.wikidata.org/entity/>
+PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+DESCRIBE ?x wd:Q42 WHERE { ?x wdt:P31 wd:Q5 . }"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"], json!([]));
+    assert_eq!(graph["computed"], json!([]));
+}
+
+#[test]
+fn test_describe_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+DESCRIBE ?person ?university WHERE { ?person wdt:P69 ?university . }"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string()
+    );
+}
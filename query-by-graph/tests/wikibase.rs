@@ -0,0 +1,809 @@
+mod common;
+
+use query_by_graph::{query_to_graph_wasm, query_to_graph_with_wikibases_wasm, vqg_to_query_wasm};
+use serde_json::{json, Value};
+use spargebra::Query;
+use common::{assert_parses, parse_graph_json, wdt, variable, educated_at_with};
+
+fn educated_at_with_start_time() -> Value {
+    let mut property = wdt("P69", "educated at");
+    property["qualifiers"] = json!([{ "property": wdt("P580", "start time"), "value": variable("?start", "start") }]);
+    property
+}
+
+#[test]
+fn test_qualifier_generates_statement_pattern() {
+    let query = vqg_to_query_wasm(&educated_at_with(educated_at_with_start_time()).to_string(), false, false);
+
+    let expected = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+
+SELECT ?person ?start ?university WHERE {
+    ?person p:P69 ?statement1 .
+    ?statement1 ps:P69 ?university .
+    # person -- [educated at] -> university
+    ?statement1 pq:P580 ?start .
+    # educated at -- [start time] -> start
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_statement_variables_are_numbered_per_edge() {
+    let mut connections = educated_at_with(educated_at_with_start_time());
+    let mut end = educated_at_with_start_time();
+    end["qualifiers"][0] = json!({ "property": wdt("P582", "end time"), "value": variable("?end", "end") });
+    end["statement"] = json!("?statement1");
+    connections[0]["properties"]
+        .as_array_mut()
+        .unwrap()
+        .push(end);
+
+    let query = vqg_to_query_wasm(&connections.to_string(), false, false);
+
+    assert!(query.contains("?person p:P69 ?statement2 .\n"), "{}", query);
+    assert!(query.contains("?statement1 pq:P582 ?end .\n"), "{}", query);
+    assert!(query.contains("?statement2 pq:P580 ?start .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_qualifier_values_are_prefixed_and_filtered() {
+    let mut property = wdt("P39", "position held");
+    property["qualifiers"] = json!([{
+        "property": wdt("P642", "of"),
+        "value": { "id": "Q2", "label": "Earth", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" } }
+    }, {
+        "property": wdt("P580", "start time"),
+        "value": {
+            "id": "?start", "label": "start", "prefix": { "iri": "", "abbreviation": "" }, "selectedForProjection": false,
+            "filters": [{ "type": "comparison", "operator": ">", "left": { "type": "function", "name": "YEAR", "arguments": [{ "type": "variable", "name": "?start" }] }, "right": { "type": "term", "value": "1900" } }]
+        }
+    }]);
+    let connections = json!([{ "source": variable("?person", "person"), "target": variable("?position", "position"), "properties": [property] }]);
+
+    let query = vqg_to_query_wasm(&connections.to_string(), false, false);
+
+    assert!(query.contains("PREFIX wd: <http://www.wikidata.org/entity/>"), "{}", query);
+    assert!(query.contains("?statement1 pq:P642 wd:Q2 .\n"), "{}", query);
+    assert!(query.contains("FILTER(YEAR(?start) > 1900)"), "{}", query);
+    assert!(query.contains("SELECT ?person ?position WHERE"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_qualifiers_on_other_wikibase_use_its_prefix_family() {
+    let mut property = json!({ "id": "P160", "label": "affiliation", "prefix": { "iri": "https://database.factgrid.de/prop/direct/", "abbreviation": "fgt" } });
+    property["qualifiers"] = json!([{ "property": { "id": "P49", "label": "begin date", "prefix": { "iri": "https://database.factgrid.de/prop/direct/", "abbreviation": "fgt" } }, "value": variable("?begin", "begin") }]);
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    assert!(query.contains("PREFIX fgp: <https://database.factgrid.de/prop/>"), "{}", query);
+    assert!(query.contains("PREFIX fgps: <https://database.factgrid.de/prop/statement/>"), "{}", query);
+    assert!(query.contains("PREFIX fgpq: <https://database.factgrid.de/prop/qualifier/>"), "{}", query);
+    assert!(query.contains("?statement1 fgps:P160 ?university .\n"), "{}", query);
+    assert!(query.contains("?statement1 fgpq:P49 ?begin .\n"), "{}", query);
+    assert!(!query.contains("fgt:"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_qualifiers_on_non_wikibase_property_are_ignored() {
+    let mut property = json!({ "id": "knows", "label": "knows", "prefix": { "iri": "http://xmlns.com/foaf/0.1/", "abbreviation": "foaf" } });
+    property["qualifiers"] = json!([{ "property": wdt("P580", "start time"), "value": variable("?start", "start") }]);
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    assert!(query.contains("?person foaf:knows ?university .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_folds_statement_pattern_into_qualified_edge() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+SELECT ?item ?uni ?start WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st pq:P580 ?start .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 1, "{}", graph);
+    let connection = &connections[0];
+    assert_eq!(connection["source"]["id"], "?item");
+    assert_eq!(connection["target"]["id"], "?uni");
+    let property = &connection["properties"][0];
+    assert_eq!(property["id"], "P69");
+    assert_eq!(property["prefix"]["abbreviation"], "wdt");
+    assert_eq!(property["statement"], "?st");
+    assert_eq!(property["qualifiers"][0]["property"]["id"], "P580");
+    assert_eq!(property["qualifiers"][0]["value"]["id"], "?start");
+    assert_eq!(property["qualifiers"][0]["value"]["selectedForProjection"], Value::Bool(true));
+}
+
+#[test]
+fn test_import_folds_blank_statement_node() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+PREFIX wd: <http://www.wikidata.org/entity/>
+SELECT ?item WHERE {
+    ?item p:P39 [ ps:P39 wd:Q11696 ; pq:P580 ?start ] .
+    ?statement1 ?p ?o .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 2, "{}", graph);
+    let property = &connections[0]["properties"][0];
+    assert_eq!(connections[0]["target"]["id"], "Q11696");
+    // ?statement1 is taken by the query itself
+    assert_eq!(property["statement"], "?statement2");
+    assert_eq!(property["qualifiers"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_import_keeps_statement_pattern_across_blocks() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+SELECT ?item ?uni WHERE {
+    ?item p:P69 ?st .
+    OPTIONAL { ?st ps:P69 ?uni . }
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    assert_eq!(graph["connections"].as_array().unwrap().len(), 2, "{}", graph);
+}
+
+#[test]
+fn test_qualified_edge_round_trip() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+SELECT ?item ?start ?uni WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st pq:P580 ?start .
+    ?st pq:P582 ?end .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+fn educated_at_stated_in() -> Value {
+    let mut property = wdt("P69", "educated at");
+    property["references"] = json!([{
+        "snaks": [{
+            "property": wdt("P248", "stated in"),
+            "value": { "id": "Q36578", "label": "GND", "prefix": { "iri": "http://www.wikidata.org/entity/", "abbreviation": "wd" } }
+        }]
+    }]);
+    property
+}
+
+#[test]
+fn test_reference_generates_derivation_pattern() {
+    let query = vqg_to_query_wasm(&educated_at_with(educated_at_stated_in()).to_string(), false, false);
+
+    let expected = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX pr: <http://www.wikidata.org/prop/reference/>
+PREFIX prov: <http://www.w3.org/ns/prov#>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX wd: <http://www.wikidata.org/entity/>
+
+SELECT ?person ?university WHERE {
+    ?person p:P69 ?statement1 .
+    ?statement1 ps:P69 ?university .
+    # person -- [educated at] -> university
+    ?statement1 prov:wasDerivedFrom ?reference1 .
+    ?reference1 pr:P248 wd:Q36578 .
+    # reference -- [stated in] -> GND
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_references_and_qualifiers_share_the_statement_node() {
+    let mut property = educated_at_stated_in();
+    property["qualifiers"] = educated_at_with_start_time()["qualifiers"].clone();
+    property["references"][0]["variable"] = json!("?source");
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    assert!(query.contains("?statement1 pq:P580 ?start .\n"), "{}", query);
+    assert!(query.contains("?statement1 prov:wasDerivedFrom ?source .\n"), "{}", query);
+    assert!(query.contains("?source pr:P248 wd:Q36578 .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_folds_references_into_edge() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX pr: <http://www.wikidata.org/prop/reference/>
+PREFIX prov: <http://www.w3.org/ns/prov#>
+PREFIX wd: <http://www.wikidata.org/entity/>
+SELECT ?item ?uni WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st prov:wasDerivedFrom [ pr:P248 wd:Q36578 ] .
+    ?st prov:wasDerivedFrom ?ref .
+    ?ref pr:P854 ?url .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 1, "{}", graph);
+    let references = connections[0]["properties"][0]["references"].as_array().unwrap();
+    assert_eq!(references.len(), 2, "{}", graph);
+    assert_eq!(references[0]["variable"], Value::Null);
+    assert_eq!(references[0]["snaks"][0]["property"]["id"], "P248");
+    assert_eq!(references[0]["snaks"][0]["value"]["id"], "Q36578");
+    assert_eq!(references[1]["variable"], "?ref");
+    assert_eq!(references[1]["snaks"][0]["value"]["id"], "?url");
+}
+
+#[test]
+fn test_referenced_edge_round_trip() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX pr: <http://www.wikidata.org/prop/reference/>
+PREFIX prov: <http://www.w3.org/ns/prov#>
+SELECT ?item ?uni ?url WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st prov:wasDerivedFrom ?ref .
+    ?ref pr:P854 ?url .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_rank_generates_rank_triple() {
+    let mut property = wdt("P69", "educated at");
+    property["rank"] = json!("preferred");
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    let expected = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+
+SELECT ?person ?university WHERE {
+    ?person p:P69 ?statement1 .
+    ?statement1 ps:P69 ?university .
+    # person -- [educated at] -> university
+    ?statement1 wikibase:rank wikibase:PreferredRank .
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_best_and_non_deprecated_ranks() {
+    let mut best = educated_at_with_start_time();
+    best["rank"] = json!("best");
+    let mut non_deprecated = wdt("P69", "educated at");
+    non_deprecated["rank"] = json!("nonDeprecated");
+    non_deprecated["statement"] = json!("?st");
+
+    let best_query = vqg_to_query_wasm(&educated_at_with(best).to_string(), false, false);
+    let non_deprecated_query = vqg_to_query_wasm(&educated_at_with(non_deprecated).to_string(), true, true);
+
+    assert!(best_query.contains("    ?statement1 a wikibase:BestRank .\n"), "{}", best_query);
+    assert!(
+        non_deprecated_query.contains("    ?st wikibase:rank ?stRank .\n    FILTER(?stRank != wikibase:DeprecatedRank)\n"),
+        "{}",
+        non_deprecated_query
+    );
+    // the label service declares the wikibase prefix once
+    assert_eq!(non_deprecated_query.matches("PREFIX wikibase:").count(), 1, "{}", non_deprecated_query);
+    assert_parses(&best_query);
+    assert_parses(&non_deprecated_query);
+}
+
+#[test]
+fn test_construct_leaves_the_rank_filter_out_of_the_template() {
+    let mut property = wdt("P69", "educated at");
+    property["rank"] = json!("nonDeprecated");
+    property["statement"] = json!("?st");
+    let graph = json!({
+        "connections": educated_at_with(property),
+        "settings": { "form": "construct" }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    let expected = r###"CONSTRUCT {
+    ?person p:P69 ?st .
+    ?st ps:P69 ?university .
+    # person -- [educated at] -> university
+    ?st wikibase:rank ?stRank .
+} WHERE {
+    ?person p:P69 ?st .
+    ?st ps:P69 ?university .
+    # person -- [educated at] -> university
+    ?st wikibase:rank ?stRank .
+    FILTER(?stRank != wikibase:DeprecatedRank)
+}"###;
+    assert!(query.ends_with(expected), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_reads_rank_constraints() {
+    let prefixes = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+"###;
+    let cases = [
+        ("?st a wikibase:BestRank .", "best"),
+        ("?st wikibase:rank wikibase:DeprecatedRank .", "deprecated"),
+        ("?st wikibase:rank ?rank . FILTER(?rank != wikibase:DeprecatedRank)", "nonDeprecated"),
+    ];
+
+    for (constraint, rank) in cases.iter() {
+        let query = format!(
+            "{}SELECT ?item ?uni WHERE {{ ?item p:P69 ?st . ?st ps:P69 ?uni . {} }}",
+            prefixes, constraint
+        );
+        let graph = parse_graph_json(&query_to_graph_wasm(&query));
+
+        let connections = graph["connections"].as_array().unwrap();
+        assert_eq!(connections.len(), 1, "{}", graph);
+        assert_eq!(connections[0]["properties"][0]["rank"], *rank, "{}", graph);
+    }
+}
+
+#[test]
+fn test_projected_statement_variable_round_trip() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+SELECT ?item ?st ?uni WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st pq:P580 ?start .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+    assert_eq!(graph["connections"][0]["properties"][0]["statementSelectedForProjection"], true);
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_ranked_edge_round_trip() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?item ?uni WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+    ?st wikibase:rank wikibase:PreferredRank .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+#[test]
+fn test_statements_mode_renders_every_wikibase_property_as_statement() {
+    let graph = json!({
+        "connections": [
+            {
+                "source": variable("?person", "person"),
+                "target": variable("?university", "university"),
+                "properties": [wdt("P69", "educated at")]
+            },
+            {
+                "source": variable("?person", "person"),
+                "target": variable("?name", "name"),
+                "properties": [{ "id": "<http://xmlns.com/foaf/0.1/name>", "label": "name", "prefix": { "iri": "", "abbreviation": "" } }]
+            }
+        ],
+        "settings": { "statementMode": "statements" }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("    ?person p:P69 ?statement1 .\n    ?statement1 ps:P69 ?university .\n"), "{}", query);
+    assert!(query.contains("    ?person <http://xmlns.com/foaf/0.1/name> ?name .\n"), "{}", query);
+    assert!(!query.contains("wdt:"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_truthy_mode_drops_unused_statement_nodes() {
+    let mut plain = wdt("P69", "educated at");
+    plain["statement"] = json!("?st");
+    let mut connections = educated_at_with(plain);
+    connections[0]["properties"]
+        .as_array_mut()
+        .unwrap()
+        .push(educated_at_with_start_time());
+    let graph = json!({ "connections": connections, "settings": { "statementMode": "truthy" } });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(query.contains("    ?person wdt:P69 ?university .\n"), "{}", query);
+    // the qualified edge still needs its statement node
+    assert!(query.contains("    ?statement1 pq:P580 ?start .\n"), "{}", query);
+    assert!(!query.contains("?st "), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_imported_statement_converts_to_truthy() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+SELECT ?item ?uni WHERE {
+    ?item p:P69 ?st .
+    ?st ps:P69 ?uni .
+}"###;
+    let mut graph = parse_graph_json(&query_to_graph_wasm(query));
+    graph["settings"]["statementMode"] = json!("truthy");
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(regenerated.contains("?item wdt:P69 ?uni .\n"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_value_node_exposes_components() {
+    let mut property = wdt("P569", "date of birth");
+    property["valueNode"] = json!({
+        "components": [
+            { "kind": "timeValue", "value": variable("?birth", "birth") },
+            { "kind": "timePrecision", "value": variable("?precision", "precision") }
+        ]
+    });
+    let connections = json!([{
+        "source": variable("?person", "person"),
+        "target": variable("?date", "date"),
+        "properties": [property]
+    }]);
+
+    let query = vqg_to_query_wasm(&connections.to_string(), false, false);
+
+    let expected = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX psv: <http://www.wikidata.org/prop/statement/value/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+
+SELECT ?birth ?date ?person ?precision WHERE {
+    ?person p:P569 ?statement1 .
+    ?statement1 ps:P569 ?date .
+    # person -- [date of birth] -> date
+    ?statement1 psv:P569 ?value1 .
+    ?value1 wikibase:timeValue ?birth .
+    # date of birth -- [timeValue] -> birth
+    ?value1 wikibase:timePrecision ?precision .
+    # date of birth -- [timePrecision] -> precision
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_qualifier_value_node_components_are_filtered() {
+    let mut property = educated_at_with_start_time();
+    let mut precision = variable("?precision", "precision");
+    precision["filters"] = json!([{
+        "type": "comparison",
+        "operator": ">=",
+        "left": { "type": "variable", "name": "?precision" },
+        "right": { "type": "term", "value": "11" }
+    }]);
+    property["qualifiers"][0]["valueNode"] = json!({
+        "variable": "?startValue",
+        "components": [{ "kind": "timePrecision", "value": precision }]
+    });
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    assert!(query.contains("PREFIX pqv: <http://www.wikidata.org/prop/qualifier/value/>\n"), "{}", query);
+    assert!(query.contains("    ?statement1 pqv:P580 ?startValue .\n"), "{}", query);
+    assert!(query.contains("    ?startValue wikibase:timePrecision ?precision .\n"), "{}", query);
+    assert!(query.contains("FILTER(?precision >= 11)"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_folds_value_nodes() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX psv: <http://www.wikidata.org/prop/statement/value/>
+PREFIX pq: <http://www.wikidata.org/prop/qualifier/>
+PREFIX pqv: <http://www.wikidata.org/prop/qualifier/value/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?item ?amount ?unit ?precision WHERE {
+    ?item p:P2067 [
+        ps:P2067 ?mass ;
+        psv:P2067 [ wikibase:quantityAmount ?amount ; wikibase:quantityUnit ?unit ] ;
+        pq:P585 ?time ;
+        pqv:P585 ?timeValue
+    ] .
+    ?timeValue wikibase:timePrecision ?precision .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 1, "{}", graph);
+    let property = &connections[0]["properties"][0];
+    assert_eq!(property["valueNode"]["variable"], Value::Null);
+    assert_eq!(property["valueNode"]["components"][0]["kind"], "quantityAmount");
+    assert_eq!(property["valueNode"]["components"][0]["value"]["id"], "?amount");
+    assert_eq!(property["valueNode"]["components"][1]["kind"], "quantityUnit");
+    let qualifier = &property["qualifiers"][0];
+    assert_eq!(qualifier["valueNode"]["variable"], "?timeValue");
+    assert_eq!(qualifier["valueNode"]["components"][0]["value"]["id"], "?precision");
+    assert_eq!(qualifier["valueNode"]["components"][0]["value"]["selectedForProjection"], Value::Bool(true));
+}
+
+#[test]
+fn test_value_node_round_trip() {
+    let query = r###"PREFIX p: <http://www.wikidata.org/prop/>
+PREFIX ps: <http://www.wikidata.org/prop/statement/>
+PREFIX psv: <http://www.wikidata.org/prop/statement/value/>
+PREFIX wikibase: <http://wikiba.se/ontology#>
+SELECT ?date ?item ?precision WHERE {
+    ?item p:P569 ?st .
+    ?st ps:P569 ?date .
+    ?st psv:P569 ?value .
+    ?value wikibase:timePrecision ?precision .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+fn date_of_death(target: Value, property: Value) -> Value {
+    json!([{
+        "source": variable("?person", "person"),
+        "target": target,
+        "properties": [property]
+    }])
+}
+
+#[test]
+fn test_some_value_target_is_filtered_with_is_blank() {
+    let mut target = variable("?death", "death");
+    target["snakType"] = json!("someValue");
+
+    let query = vqg_to_query_wasm(&date_of_death(target, wdt("P570", "date of death")).to_string(), false, false);
+
+    let expected = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+
+SELECT ?death ?person WHERE {
+    ?person wdt:P570 ?death .
+    # person -- [date of death] -> death
+    FILTER(isBlank(?death))
+}"###;
+    assert_eq!(query, expected);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_no_value_target_renders_class_membership() {
+    let mut target = variable("?child", "child");
+    target["snakType"] = json!("noValue");
+    let mut statement = wdt("P40", "child");
+    statement["statement"] = json!("?st");
+
+    let truthy = vqg_to_query_wasm(&date_of_death(target.clone(), wdt("P40", "child")).to_string(), false, false);
+    let full = vqg_to_query_wasm(&date_of_death(target, statement).to_string(), false, false);
+
+    let expected = r###"PREFIX wdno: <http://www.wikidata.org/prop/novalue/>
+
+SELECT ?person WHERE {
+    ?person a wdno:P40 .
+    # person -- [child] -> child
+}"###;
+    assert_eq!(truthy, expected);
+    assert!(full.contains("    ?person p:P40 ?st .\n    ?st a wdno:P40 .\n"), "{}", full);
+    assert!(!full.contains("PREFIX ps:"), "{}", full);
+    assert_parses(&truthy);
+    assert_parses(&full);
+}
+
+#[test]
+fn test_import_recognises_special_values() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+PREFIX wdno: <http://www.wikidata.org/prop/novalue/>
+PREFIX p: <http://www.wikidata.org/prop/>
+SELECT ?person ?death WHERE {
+    ?person wdt:P570 ?death .
+    FILTER(isBlank(?death))
+    ?person wdt:P1196 [] .
+    ?person a wdno:P40 .
+    ?person p:P26 ?st .
+    ?st a wdno:P26 .
+}"###;
+
+    let graph = parse_graph_json(&query_to_graph_wasm(query));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 4, "{}", graph);
+    assert_eq!(connections[0]["target"]["snakType"], "someValue");
+    assert_eq!(connections[0]["target"]["filters"], json!([]));
+    // a blank node without isBlank is an existential variable, not an unknown value
+    assert_eq!(connections[1]["target"]["snakType"], Value::Null);
+    assert!(connections[1]["target"]["id"].as_str().unwrap().starts_with("_:"), "{}", graph);
+    assert_eq!(connections[2]["target"]["snakType"], "noValue");
+    assert_eq!(connections[2]["properties"][0]["id"], "P40");
+    assert_eq!(connections[3]["target"]["snakType"], "noValue");
+    assert_eq!(connections[3]["properties"][0]["statement"], "?st");
+}
+
+#[test]
+fn test_blank_node_target_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+SELECT ?item WHERE {
+    ?item wdt:P570 [] .
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert!(!regenerated.contains("isBlank"), "{}", regenerated);
+    assert!(!regenerated.contains("?someValue"), "{}", regenerated);
+    assert!(regenerated.contains("    ?item wdt:P570 _:"), "{}", regenerated);
+    assert_parses(&regenerated);
+}
+
+#[test]
+fn test_special_values_round_trip() {
+    let query = r###"PREFIX wdt: <http://www.wikidata.org/prop/direct/>
+PREFIX wdno: <http://www.wikidata.org/prop/novalue/>
+SELECT ?death ?person WHERE {
+    ?person wdt:P570 ?death .
+    ?person a wdno:P40 .
+    FILTER(isBlank(?death))
+}"###;
+
+    let regenerated = vqg_to_query_wasm(&query_to_graph_wasm(query), false, false);
+
+    assert_eq!(
+        Query::parse(&regenerated, None).unwrap().to_string(),
+        Query::parse(query, None).unwrap().to_string(),
+        "{}",
+        regenerated
+    );
+}
+
+fn custom_wikibase() -> Value {
+    json!({
+        "name": "Example",
+        "base": "https://example.wikibase.cloud/",
+        "prefixes": {
+            "entity": "exd", "direct": "exdt", "claim": "exp", "statement": "exps",
+            "statementValue": "expsv", "qualifier": "expq", "qualifierValue": "expqv",
+            "reference": "expr", "noValue": "exdno", "statementNode": "exds", "referenceNode": "exdref"
+        },
+        "languages": ["fr"]
+    })
+}
+
+#[test]
+fn test_registered_family_gives_the_abbreviations() {
+    // the caller's abbreviation is irrelevant for a registered instance
+    let mut property = json!({ "id": "P4", "label": "author", "prefix": { "iri": "http://data.mimotext.uni-trier.de/prop/direct/", "abbreviation": "mimo" } });
+    property["qualifiers"] = json!([{ "property": { "id": "P12", "label": "stated in", "prefix": { "iri": "", "abbreviation": "" } }, "value": variable("?source", "source") }]);
+
+    let query = vqg_to_query_wasm(&educated_at_with(property).to_string(), false, false);
+
+    assert!(query.contains("PREFIX mmp: <http://data.mimotext.uni-trier.de/prop/>"), "{}", query);
+    assert!(query.contains("?person mmp:P4 ?statement1 .\n"), "{}", query);
+    assert!(query.contains("?statement1 mmps:P4 ?university .\n"), "{}", query);
+    assert!(query.contains("?statement1 mmpq:P12 ?source .\n"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_custom_family_from_settings() {
+    let mut property = json!({ "id": "P7", "label": "member of", "prefix": { "iri": "https://example.wikibase.cloud/prop/direct/", "abbreviation": "exdt" } });
+    property["rank"] = json!("preferred");
+    let graph = json!({
+        "connections": educated_at_with(property),
+        "settings": { "wikibases": [custom_wikibase()], "dataSource": "example" }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), true, true);
+
+    assert!(query.contains("PREFIX exp: <https://example.wikibase.cloud/prop/>"), "{}", query);
+    assert!(query.contains("PREFIX exps: <https://example.wikibase.cloud/prop/statement/>"), "{}", query);
+    assert!(query.contains("?statement1 exps:P7 ?university .\n"), "{}", query);
+    // the label languages default to the ones of the data source
+    assert!(query.contains("bd:serviceParam wikibase:language \"fr\"."), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_data_source_label_languages() {
+    let graph = json!({
+        "connections": educated_at_with(wdt("P69", "educated at")),
+        "settings": { "dataSource": "FactGrid", "labels": { "strategy": "rdfsLabel" } }
+    });
+
+    let query = vqg_to_query_wasm(&graph.to_string(), false, false);
+
+    assert!(
+        query.contains("OPTIONAL { ?person rdfs:label ?personLabel . FILTER(LANG(?personLabel) = \"de\") }\n"),
+        "{}",
+        query
+    );
+    assert!(query.contains("FILTER(LANG(?personLabel) = \"en\")"), "{}", query);
+    assert_parses(&query);
+}
+
+#[test]
+fn test_import_with_custom_wikibases() {
+    let query = r###"PREFIX exp: <https://example.wikibase.cloud/prop/>
+PREFIX exps: <https://example.wikibase.cloud/prop/statement/>
+PREFIX exds: <https://example.wikibase.cloud/entity/statement/>
+SELECT ?item ?group WHERE {
+    ?item exp:P7 exds:Q1-abc .
+    exds:Q1-abc exps:P7 ?group .
+}"###;
+    let wikibases = json!([custom_wikibase()]).to_string();
+
+    let graph = parse_graph_json(&query_to_graph_with_wikibases_wasm(query, &wikibases));
+
+    let connections = graph["connections"].as_array().unwrap();
+    assert_eq!(connections.len(), 1, "{}", graph);
+    // the concrete statement is classified as statement node
+    assert_eq!(
+        connections[0]["properties"][0]["statement"],
+        "<https://example.wikibase.cloud/entity/statement/Q1-abc>"
+    );
+    assert_eq!(graph["settings"]["wikibases"], json!([custom_wikibase()]));
+
+    let regenerated = vqg_to_query_wasm(&graph.to_string(), false, false);
+    assert!(regenerated.contains("?item exp:P7 <https://example.wikibase.cloud/entity/statement/Q1-abc> .\n"), "{}", regenerated);
+    assert_parses(&regenerated);
+}